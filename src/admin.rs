@@ -0,0 +1,217 @@
+// Operator interface on a local Unix socket, separate from the MCP
+// transport. Each connection sends one command line and gets one JSON
+// reply back:
+//
+//   dump-state                     executions, configuration and uptime
+//   list-children                  sudo/bpftrace processes per execution
+//   force-cleanup [execution_id]   cancel running executions and drop them
+//   rotate-logs                    move the log file aside and reopen it
+//
+// `bpftrace-mcp-server admin <command>` is the matching client.
+
+use crate::{logfile::LogFile, targeting, transport, BpftraceServer, ExecutionBuffer};
+use serde_json::{json, Value};
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::Shutdown,
+    os::unix::net::UnixStream,
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{runtime::Handle, time::sleep};
+use tracing::{info, warn};
+
+// Requests are served one at a time, so a client that connects and stalls
+// must not hold the socket for longer than this
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Longer than any command line
+const MAX_COMMAND_BYTES: u64 = 4096;
+
+const COMMANDS: &[&str] = &["dump-state", "list-children", "force-cleanup [execution_id]", "rotate-logs"];
+
+pub fn spawn(path: &Path, server: BpftraceServer, logs: Option<Arc<LogFile>>) -> io::Result<()> {
+    let listener = transport::bind_private(path)?;
+    info!("Admin socket listening on {}", path.display());
+
+    let runtime = Handle::current();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let result = stream.and_then(|stream| {
+                stream.set_read_timeout(Some(IO_TIMEOUT))?;
+                stream.set_write_timeout(Some(IO_TIMEOUT))?;
+                let mut command = String::new();
+                BufReader::new((&stream).take(MAX_COMMAND_BYTES)).read_line(&mut command)?;
+                let reply = runtime.block_on(dispatch(command.trim(), &server, logs.as_deref()));
+                writeln!(&stream, "{}", reply)
+            });
+            if let Err(e) = result {
+                warn!("admin request failed: {}", e);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+pub fn run_client(socket: &Path, command: &[String]) -> io::Result<String> {
+    let mut stream = UnixStream::connect(socket)?;
+    writeln!(stream, "{}", command.join(" "))?;
+    stream.shutdown(Shutdown::Write)?;
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    Ok(reply)
+}
+
+async fn dispatch(command: &str, server: &BpftraceServer, logs: Option<&LogFile>) -> Value {
+    let mut parts = command.split_whitespace();
+    match parts.next() {
+        Some("dump-state") => dump_state(server, logs).await,
+        Some("list-children") => list_children(server).await,
+        Some("force-cleanup") => force_cleanup(server, parts.next()).await,
+        Some("rotate-logs") => rotate_logs(logs),
+        Some("help") | None => json!({"commands": COMMANDS}),
+        Some(other) => json!({
+            "error": format!("unknown command '{}'", other),
+            "commands": COMMANDS
+        }),
+    }
+}
+
+fn executions(server: &BpftraceServer) -> Vec<(String, ExecutionBuffer)> {
+    let mut executions: Vec<(String, ExecutionBuffer)> = server
+        .execution_buffers
+        .iter()
+        .map(|entry| (entry.key().clone(), entry.value().clone()))
+        .collect();
+    executions.sort_by_key(|(_, buffer)| buffer.creation_time);
+    executions
+}
+
+async fn dump_state(server: &BpftraceServer, logs: Option<&LogFile>) -> Value {
+    let mut states = Vec::new();
+    for (execution_id, buffer) in executions(server) {
+        states.push(json!({
+            "execution_id": execution_id,
+            "status": buffer.status.lock().await.clone(),
+            "creation_time": buffer.creation_time,
+            "completion_time": *buffer.completion_time.lock().await,
+            "timeout": buffer.timeout.as_secs(),
+            "long_running": buffer.long_running,
+            "lines_total": buffer.line_count().await,
+            "lost_events": *buffer.lost_events.lock().await,
+            "child_pids": buffer.child_pids.lock().await.clone(),
+            "target_pids": buffer.target_pids,
+            "spill_path": buffer.spill.as_ref().map(|spill| spill.path().display().to_string()),
+        }));
+    }
+
+    json!({
+        "pid": std::process::id(),
+        "uptime_secs": server.started_at.elapsed().as_secs(),
+        "config": *server.config,
+        "log_file": logs.map(|logs| logs.path().display().to_string()),
+        "subscriptions": server.subscriptions.len(),
+        "executions": states,
+    })
+}
+
+async fn list_children(server: &BpftraceServer) -> Value {
+    let mut children = Vec::new();
+    for (execution_id, buffer) in executions(server) {
+        for pid in buffer.child_pids.lock().await.iter() {
+            children.push(json!({
+                "execution_id": execution_id,
+                "pid": pid,
+                "children": targeting::child_pids(*pid),
+            }));
+        }
+    }
+    json!({"children": children})
+}
+
+async fn force_cleanup(server: &BpftraceServer, only: Option<&str>) -> Value {
+    let selected: Vec<(String, ExecutionBuffer)> = executions(server)
+        .into_iter()
+        .filter(|(execution_id, _)| only.is_none_or(|only| only == execution_id))
+        .collect();
+    if let Some(only) = only {
+        if selected.is_empty() {
+            return json!({"error": format!("execution '{}' not found", only)});
+        }
+    }
+
+    // The bpftrace processes under each sudo, remembered before cancelling
+    // because sudo drops out of child_pids once it exits
+    let mut tracers = Vec::new();
+    for (_, buffer) in &selected {
+        let tracer_pids: Vec<u32> = buffer
+            .child_pids
+            .lock()
+            .await
+            .iter()
+            .flat_map(|pid| targeting::child_pids(*pid))
+            .collect();
+        tracers.push(tracer_pids);
+    }
+
+    let mut cancelled = Vec::new();
+    for (execution_id, buffer) in &selected {
+        if *buffer.status.lock().await == "running" {
+            buffer.cancel.cancel();
+            cancelled.push(execution_id.clone());
+        }
+    }
+
+    // Give cancelled executions time to stop bpftrace and reap sudo; the
+    // run loop allows SIGTERM a grace period before escalating
+    for _ in 0..50 {
+        let mut still_running = false;
+        for ((_, buffer), tracer_pids) in selected.iter().zip(&tracers) {
+            still_running |= *buffer.status.lock().await == "running"
+                || tracer_pids.iter().any(|pid| targeting::is_tracer_running(*pid, buffer.tracer));
+        }
+        if !still_running {
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+
+    // An execution whose tracer survived is kept so it stays visible in
+    // dump-state instead of leaking a root bpftrace silently
+    let mut removed = Vec::new();
+    let mut not_removed = Vec::new();
+    for ((execution_id, buffer), tracer_pids) in selected.iter().zip(&tracers) {
+        let surviving: Vec<u32> = tracer_pids
+            .iter()
+            .copied()
+            .filter(|pid| targeting::is_tracer_running(*pid, buffer.tracer))
+            .collect();
+        if *buffer.status.lock().await != "running" && surviving.is_empty() {
+            BpftraceServer::remove_execution(&server.execution_buffers, execution_id).await;
+            removed.push(execution_id.clone());
+        } else {
+            not_removed.push(json!({
+                "execution_id": execution_id,
+                "status": buffer.status.lock().await.clone(),
+                "tracer_pids": surviving,
+            }));
+        }
+    }
+
+    json!({"cancelled": cancelled, "removed": removed, "not_removed": not_removed})
+}
+
+fn rotate_logs(logs: Option<&LogFile>) -> Value {
+    match logs {
+        Some(logs) => match logs.rotate() {
+            Ok(rotated) => json!({
+                "log_file": logs.path().display().to_string(),
+                "rotated_to": rotated.display().to_string()
+            }),
+            Err(e) => json!({"error": format!("failed to rotate logs: {}", e)}),
+        },
+        None => json!({"error": "no log file configured (set BPFTRACE_MCP_LOG_FILE)"}),
+    }
+}
@@ -0,0 +1,97 @@
+// What an execution asked to attach to versus what bpftrace attached.
+
+use crate::program;
+use rmcp::schemars::{self, JsonSchema};
+use serde::{Deserialize, Serialize};
+
+// Cap on probe names kept per wildcard so a broad match stays readable
+const MAX_MATCH_NAMES: usize = 50;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RequestedProbe {
+    pub probe: String,
+    pub wildcard: bool,
+    // Number of probes the wildcard expands to, once resolved
+    pub matched: Option<usize>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub matches: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct AttachReport {
+    pub requested: Vec<RequestedProbe>,
+    // Total reported by bpftrace's "Attaching N probes" message(s)
+    pub attached_probes: Option<u64>,
+    pub wildcards_resolved: bool,
+    pub warnings: Vec<String>,
+}
+
+fn is_wildcard(probe: &str) -> bool {
+    probe.contains(['*', '?'])
+}
+
+impl AttachReport {
+    pub fn for_program(source: &str) -> Self {
+        let requested = program::parse_clauses(source)
+            .iter()
+            .filter(|clause| !clause.is_task_independent())
+            .flat_map(|clause| clause.probes.iter())
+            .map(|probe| RequestedProbe {
+                probe: probe.clone(),
+                wildcard: is_wildcard(probe),
+                matched: None,
+                matches: Vec::new(),
+            })
+            .collect();
+
+        Self {
+            requested,
+            ..Self::default()
+        }
+    }
+
+    pub fn wildcards(&self) -> Vec<String> {
+        self.requested
+            .iter()
+            .filter(|probe| probe.wildcard)
+            .map(|probe| probe.probe.clone())
+            .collect()
+    }
+
+    pub fn wildcard_matches(&self) -> usize {
+        self.requested.iter().filter_map(|probe| probe.matched).sum()
+    }
+
+    pub fn add_attached(&mut self, count: u64) {
+        *self.attached_probes.get_or_insert(0) += count;
+    }
+
+    pub fn set_matches(&mut self, probe: &str, matches: Vec<String>) {
+        for requested in self.requested.iter_mut().filter(|r| r.probe == probe) {
+            requested.matched = Some(matches.len());
+            requested.matches = matches.iter().take(MAX_MATCH_NAMES).cloned().collect();
+        }
+    }
+
+    // Copy of the report with warnings derived from the current state
+    pub fn snapshot(&self) -> Self {
+        let mut report = self.clone();
+        report.warnings = self
+            .requested
+            .iter()
+            .filter(|probe| probe.matched == Some(0))
+            .map(|probe| {
+                format!(
+                    "Wildcard probe '{}' matches no probes on this kernel; its action block will never run",
+                    probe.probe
+                )
+            })
+            .collect();
+        if self.attached_probes == Some(0) {
+            report
+                .warnings
+                .push("bpftrace attached 0 probes; the program cannot produce events".to_string());
+        }
+        report
+    }
+}
@@ -0,0 +1,465 @@
+// Self-contained snapshot of an execution for sharing: program, metadata,
+// a description of the machine it ran on, the full output and the maps and
+// histograms parsed from it. Bundles are plain JSON files; importing one
+// recreates the execution in replay mode so the usual read tools work on
+// another machine.
+
+use crate::{
+    attach::AttachReport,
+    output::{self, Histogram, HistogramParser},
+    summary, ExecutionBuffer,
+};
+use rmcp::schemars::{self, JsonSchema};
+use serde::{
+    ser::{Error as _, SerializeSeq},
+    Deserialize, Serialize, Serializer,
+};
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{fs, io::AsyncReadExt, sync::Mutex};
+
+pub const FORMAT: &str = "bpftrace-mcp-bundle";
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SystemInfo {
+    pub hostname: String,
+    pub kernel: String,
+    pub server_version: String,
+    // Output of `bpftrace --info`, when it could be collected
+    pub bpftrace_info: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct MapValue {
+    pub map: String,
+    pub key: String,
+    pub value: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BundleExecution {
+    pub execution_id: String,
+    pub status: String,
+    pub program: String,
+    pub timeout: u64,
+    pub creation_time: u64,
+    pub completion_time: Option<u64>,
+    pub error_message: Option<String>,
+    pub target_pids: Vec<u32>,
+    pub per_pid_attach: bool,
+    pub long_running: bool,
+    pub lost_events: u64,
+    pub attach_report: AttachReport,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bundle<O = Vec<String>> {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: u64,
+    pub system_info: SystemInfo,
+    pub execution: BundleExecution,
+    // Events per second since the execution started
+    pub event_rate: Vec<u64>,
+    // Latest value of every scalar map entry printed
+    pub maps: Vec<MapValue>,
+    pub histograms: Vec<Histogram>,
+    // Last, so the parsed sections above come first in the file
+    pub output: O,
+}
+
+// Output of an execution being exported. A spill file is serialized
+// straight from disk rather than loaded into memory first.
+#[derive(Debug)]
+pub enum ExportOutput {
+    Memory(Vec<String>),
+    // The first `lines` lines of the file, as many as were parsed for maps
+    // and histograms even if a running execution has appended more since
+    Spill { path: PathBuf, lines: usize },
+}
+
+impl ExportOutput {
+    pub fn line_count(&self) -> usize {
+        match self {
+            ExportOutput::Memory(lines) => lines.len(),
+            ExportOutput::Spill { lines, .. } => *lines,
+        }
+    }
+}
+
+impl Serialize for ExportOutput {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ExportOutput::Memory(lines) => lines.serialize(serializer),
+            ExportOutput::Spill { path, lines } => {
+                let file = std::fs::File::open(path).map_err(S::Error::custom)?;
+                let mut seq = serializer.serialize_seq(Some(*lines))?;
+                for line in io::BufReader::new(file).lines().take(*lines) {
+                    seq.serialize_element(&line.map_err(S::Error::custom)?)?;
+                }
+                seq.end()
+            }
+        }
+    }
+}
+
+// Fails writes once more than `max_bytes` have gone through
+struct LimitedWriter<W> {
+    inner: W,
+    written: u64,
+    max_bytes: u64,
+}
+
+impl<W: Write> Write for LimitedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written + buf.len() as u64 > self.max_bytes {
+            return Err(too_large(self.max_bytes));
+        }
+        let written = self.inner.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn too_large(max_bytes: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::FileTooLarge,
+        format!("bundle is larger than {} bytes (BPFTRACE_MCP_MAX_BUNDLE_BYTES)", max_bytes),
+    )
+}
+
+// Where a replayed execution came from
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ReplayInfo {
+    pub bundle: String,
+    pub original_execution_id: String,
+    pub exported_at: u64,
+    // Replays expire like finished executions, counted from the import
+    pub imported_at: u64,
+    pub system_info: SystemInfo,
+}
+
+impl ReplayInfo {
+    // Whether this replay was imported from the same export of `bundle`
+    pub fn is_import_of(&self, path: &Path, exported_at: u64, original_execution_id: &str) -> bool {
+        self.bundle == path.display().to_string()
+            && self.exported_at == exported_at
+            && self.original_execution_id == original_execution_id
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+pub fn system_info(bpftrace_info: Option<String>) -> SystemInfo {
+    SystemInfo {
+        hostname: std::fs::read_to_string("/proc/sys/kernel/hostname")
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+        kernel: crate::probe_cache::kernel_version(),
+        server_version: env!("CARGO_PKG_VERSION").to_string(),
+        bpftrace_info,
+    }
+}
+
+impl Bundle<ExportOutput> {
+    pub async fn from_execution(
+        execution_id: &str,
+        buffer: &ExecutionBuffer,
+        system_info: SystemInfo,
+    ) -> io::Result<Self> {
+        let mut maps: BTreeMap<(String, String), f64> = BTreeMap::new();
+        let mut histograms = HistogramParser::default();
+        let mut kept = Vec::new();
+        let mut line_count = 0;
+        let mut lines = buffer.output_lines().await?;
+        while let Some(line) = lines.next_line().await? {
+            let stripped = summary::strip_tag(&line);
+            for sample in output::parse_map_samples(stripped) {
+                maps.insert((sample.map, sample.key), sample.value);
+            }
+            histograms.add(stripped);
+            line_count += 1;
+            if buffer.spill.is_none() {
+                kept.push(line);
+            }
+        }
+        let output = match &buffer.spill {
+            Some(spill) => ExportOutput::Spill {
+                path: spill.path().to_path_buf(),
+                lines: line_count,
+            },
+            None => ExportOutput::Memory(kept),
+        };
+
+        Ok(Self {
+            format: FORMAT.to_string(),
+            version: VERSION,
+            exported_at: now(),
+            system_info,
+            execution: BundleExecution {
+                execution_id: execution_id.to_string(),
+                status: buffer.status.lock().await.clone(),
+                program: buffer.program.clone(),
+                timeout: buffer.timeout.as_secs(),
+                creation_time: buffer.creation_time,
+                completion_time: *buffer.completion_time.lock().await,
+                error_message: buffer.error_message.lock().await.clone(),
+                target_pids: buffer.target_pids.clone(),
+                per_pid_attach: buffer.per_pid_attach,
+                long_running: buffer.long_running,
+                lost_events: *buffer.lost_events.lock().await,
+                attach_report: buffer.attach_report.lock().await.snapshot(),
+            },
+            event_rate: buffer.event_rate.lock().await.clone(),
+            maps: maps
+                .into_iter()
+                .map(|((map, key), value)| MapValue { map, key, value })
+                .collect(),
+            histograms: histograms.finish(),
+            output,
+        })
+    }
+
+    // Stream the bundle to `path`, giving up (and removing the partial
+    // file) once it grows past `max_bytes`
+    pub async fn write(self, path: &Path, max_bytes: u64) -> io::Result<u64> {
+        if let Some(parent) = path.parent() {
+            crate::config::ensure_private_dir(parent)?;
+        }
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            let mut writer = LimitedWriter {
+                inner: io::BufWriter::new(std::fs::File::create(&path)?),
+                written: 0,
+                max_bytes,
+            };
+            let result = serde_json::to_writer_pretty(&mut writer, &self)
+                .map_err(io::Error::from)
+                .and_then(|()| writer.flush());
+            match result {
+                Ok(()) => Ok(writer.written),
+                Err(e) => {
+                    let _ = std::fs::remove_file(&path);
+                    Err(e)
+                }
+            }
+        })
+        .await?
+    }
+}
+
+impl Bundle {
+    // Bundles larger than `max_bytes` are rejected before being read
+    pub async fn read(path: &Path, max_bytes: u64) -> io::Result<Self> {
+        let file = fs::File::open(path).await?;
+        if file.metadata().await?.len() > max_bytes {
+            return Err(too_large(max_bytes));
+        }
+        let mut contents = Vec::new();
+        file.take(max_bytes + 1).read_to_end(&mut contents).await?;
+        if contents.len() as u64 > max_bytes {
+            return Err(too_large(max_bytes));
+        }
+        let bundle: Self = serde_json::from_slice(&contents)?;
+        if bundle.format != FORMAT || bundle.version > VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "not a {} version {} file (found {} version {})",
+                    FORMAT, VERSION, bundle.format, bundle.version
+                ),
+            ));
+        }
+        Ok(bundle)
+    }
+
+    // Recreate the execution as a finished, read-only buffer
+    pub fn into_buffer(self, path: PathBuf) -> ExecutionBuffer {
+        let execution = self.execution;
+        let mut buffer = ExecutionBuffer::new(
+            self.output.len().max(10000),
+            Duration::from_secs(execution.timeout),
+        );
+        // A snapshot taken mid-run will never finish in replay
+        let (status, error_message) = if execution.status == "running" {
+            ("interrupted".to_string(), Some("Exported while the execution was still running".to_string()))
+        } else {
+            (execution.status, execution.error_message)
+        };
+        buffer.creation_time = execution.creation_time;
+        buffer.status = Arc::new(Mutex::new(status));
+        buffer.completion_time = Arc::new(Mutex::new(Some(execution.completion_time.unwrap_or(self.exported_at))));
+        buffer.error_message = Arc::new(Mutex::new(error_message));
+        buffer.lost_events = Arc::new(Mutex::new(execution.lost_events));
+        buffer.program = execution.program;
+        buffer.target_pids = execution.target_pids;
+        buffer.per_pid_attach = execution.per_pid_attach;
+        buffer.long_running = execution.long_running;
+        buffer.attach_report = Arc::new(Mutex::new(execution.attach_report));
+        buffer.lines = Arc::new(Mutex::new(self.output));
+        buffer.event_rate = Arc::new(Mutex::new(self.event_rate));
+        buffer.map_values = Arc::new(Mutex::new(
+            self.maps
+                .into_iter()
+                .map(|entry| ((entry.map, entry.key, None), entry.value))
+                .collect(),
+        ));
+        buffer.replay = Some(Arc::new(ReplayInfo {
+            bundle: path.display().to_string(),
+            original_execution_id: execution.execution_id,
+            exported_at: self.exported_at,
+            imported_at: now(),
+            system_info: self.system_info,
+        }));
+        buffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bpftrace-mcp-bundle-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn test_system_info() -> SystemInfo {
+        SystemInfo {
+            hostname: "tracehost".to_string(),
+            kernel: "6.8.0".to_string(),
+            server_version: "0.1.0".to_string(),
+            bpftrace_info: None,
+        }
+    }
+
+    async fn buffer_with(lines: &[&str]) -> ExecutionBuffer {
+        let mut buffer = ExecutionBuffer::new(100, Duration::from_secs(30));
+        buffer.program = "kprobe:vfs_read { @bytes[comm] = sum(arg2); }".to_string();
+        buffer.target_pids = vec![42];
+        for line in lines {
+            buffer.add_line(line.to_string()).await;
+        }
+        buffer
+    }
+
+    #[tokio::test]
+    async fn round_trips_through_a_file() {
+        let dir = temp_dir("round-trip");
+        let path = dir.join("trace.json");
+        let lines = ["@bytes[nginx]: 4096", "@lat:", "[4K, 8K)     5 |@@@@@     |", "", "@bytes[nginx]: 8192"];
+        let buffer = buffer_with(&lines).await;
+        buffer.mark_completed().await;
+
+        let exported = Bundle::from_execution("exec_1", &buffer, test_system_info()).await.unwrap();
+        assert_eq!(exported.output.line_count(), lines.len());
+        let exported_at = exported.exported_at;
+        let written = exported.write(&path, 1 << 20).await.unwrap();
+        assert_eq!(written, std::fs::metadata(&path).unwrap().len());
+
+        let bundle = Bundle::read(&path, 1 << 20).await.unwrap();
+        assert_eq!((bundle.format.as_str(), bundle.version), (FORMAT, VERSION));
+        assert_eq!(bundle.exported_at, exported_at);
+        assert_eq!(bundle.system_info.hostname, "tracehost");
+        assert_eq!(bundle.execution.execution_id, "exec_1");
+        assert_eq!(bundle.execution.status, "completed");
+        assert_eq!(bundle.execution.program, buffer.program);
+        assert_eq!(bundle.execution.target_pids, vec![42]);
+        assert_eq!(bundle.output, lines);
+        // Only the latest value of each map entry is kept
+        assert_eq!(bundle.maps.len(), 1);
+        assert_eq!((bundle.maps[0].key.as_str(), bundle.maps[0].value), ("nginx", 8192.0));
+        assert_eq!(bundle.histograms.len(), 1);
+        assert_eq!(bundle.histograms[0].buckets[0].count, 5);
+
+        let replay = bundle.into_buffer(path.clone());
+        assert_eq!(*replay.status.lock().await, "completed");
+        assert_eq!(*replay.lines.lock().await, lines);
+        assert!(replay.replay.as_ref().unwrap().is_import_of(&path, exported_at, "exec_1"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn streams_spilled_output_as_of_the_export() {
+        let dir = temp_dir("spill");
+        let mut buffer = buffer_with(&[]).await;
+        buffer.spill = Some(crate::spill::SpillFile::create(&dir, "exec_2", u64::MAX).await.unwrap());
+        for i in 0..3 {
+            buffer.add_line(format!("line {}", i)).await;
+        }
+
+        let exported = Bundle::from_execution("exec_2", &buffer, test_system_info()).await.unwrap();
+        assert!(matches!(exported.output, ExportOutput::Spill { lines: 3, .. }));
+        // Lines appended after parsing are not part of the bundle
+        buffer.add_line("line 3".to_string()).await;
+        let path = dir.join("trace.json");
+        exported.write(&path, 1 << 20).await.unwrap();
+
+        let bundle = Bundle::read(&path, 1 << 20).await.unwrap();
+        assert_eq!(bundle.output, ["line 0", "line 1", "line 2"]);
+        assert_eq!(bundle.execution.status, "running");
+
+        // A snapshot of a running execution replays as interrupted
+        let replay = bundle.into_buffer(path);
+        assert_eq!(*replay.status.lock().await, "interrupted");
+        assert!(replay.error_message.lock().await.is_some());
+        assert!(replay.completion_time.lock().await.is_some());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn enforces_max_bytes() {
+        let dir = temp_dir("max-bytes");
+        let path = dir.join("trace.json");
+        let buffer = buffer_with(&["x".repeat(500).as_str()]).await;
+
+        // A write that outgrows the limit leaves no partial file behind
+        let exported = Bundle::from_execution("exec_3", &buffer, test_system_info()).await.unwrap();
+        let error = exported.write(&path, 256).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::FileTooLarge);
+        assert!(!path.exists());
+
+        let exported = Bundle::from_execution("exec_3", &buffer, test_system_info()).await.unwrap();
+        let written = exported.write(&path, 1 << 20).await.unwrap();
+        let error = Bundle::read(&path, written - 1).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::FileTooLarge);
+        assert!(Bundle::read(&path, written).await.is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_other_formats_and_newer_versions() {
+        let dir = temp_dir("format");
+        let path = dir.join("trace.json");
+        let buffer = buffer_with(&["hello"]).await;
+        let exported = Bundle::from_execution("exec_4", &buffer, test_system_info()).await.unwrap();
+        exported.write(&path, 1 << 20).await.unwrap();
+        let original: serde_json::Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+
+        for (field, value) in [("format", serde_json::json!("other")), ("version", serde_json::json!(VERSION + 1))] {
+            let mut modified = original.clone();
+            modified[field] = value;
+            std::fs::write(&path, serde_json::to_vec(&modified).unwrap()).unwrap();
+            let error = Bundle::read(&path, 1 << 20).await.unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", field);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,238 @@
+use serde::Serialize;
+use std::{
+    fs::DirBuilder,
+    io,
+    os::unix::fs::{DirBuilderExt, MetadataExt},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+// Operator-controlled limits, read from the environment (or .env) at startup.
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerConfig {
+    // Upper bound for the timeout of regular executions (BPFTRACE_MCP_MAX_TIMEOUT)
+    pub max_timeout: u64,
+    // Whether clients may request long_running executions (BPFTRACE_MCP_ALLOW_LONG_RUNNING)
+    pub allow_long_running: bool,
+    // Upper bound for the timeout of long-running executions (BPFTRACE_MCP_LONG_RUNNING_MAX_TIMEOUT)
+    pub long_running_max_timeout: u64,
+    // How many long-running executions may be active at once (BPFTRACE_MCP_MAX_LONG_RUNNING)
+    pub max_long_running: usize,
+    // Where long-running executions spill their full output (BPFTRACE_MCP_SPILL_DIR)
+    pub spill_dir: PathBuf,
+    // Largest spill file one execution may write (BPFTRACE_MCP_MAX_SPILL_BYTES)
+    pub max_spill_bytes: u64,
+    // Largest accepted program source (BPFTRACE_MCP_MAX_PROGRAM_BYTES)
+    pub max_program_bytes: usize,
+    // Most probes a program may list, counting each wildcard once (BPFTRACE_MCP_MAX_PROBES)
+    pub max_probes: usize,
+    // Most probes all wildcards together may expand to (BPFTRACE_MCP_MAX_WILDCARD_PROBES)
+    pub max_wildcard_probes: usize,
+    // Most processes one execution may target (BPFTRACE_MCP_MAX_TARGET_PIDS)
+    pub max_target_pids: usize,
+    // Most bpftrace instances one execution may start in per-PID mode (BPFTRACE_MCP_MAX_ATTACH_PIDS)
+    pub max_attach_pids: usize,
+    // Deadline in seconds for discovery commands such as `bpftrace -l` (BPFTRACE_MCP_DISCOVERY_TIMEOUT)
+    pub discovery_timeout_secs: u64,
+    // Address of the Prometheus /metrics endpoint, disabled when unset (BPFTRACE_MCP_METRICS_ADDR)
+    pub metrics_addr: Option<String>,
+    // Directory of per-process execution journals used for crash recovery,
+    // disabled when set to an empty value (BPFTRACE_MCP_JOURNAL_DIR)
+    pub journal_dir: Option<PathBuf>,
+    // Unix socket for the operator admin interface, disabled when unset (BPFTRACE_MCP_ADMIN_SOCKET)
+    pub admin_socket: Option<PathBuf>,
+    // Where export_bundle writes execution bundles (BPFTRACE_MCP_BUNDLE_DIR)
+    pub bundle_dir: PathBuf,
+    // Largest bundle export_bundle writes or import_bundle reads (BPFTRACE_MCP_MAX_BUNDLE_BYTES)
+    pub max_bundle_bytes: u64,
+    // Unix socket serving MCP alongside stdio, sharing the same executions,
+    // disabled when unset (BPFTRACE_MCP_SOCKET)
+    pub mcp_socket: Option<PathBuf>,
+    // File to write logs to instead of stdout (BPFTRACE_MCP_LOG_FILE)
+    pub log_file: Option<PathBuf>,
+    // Tracing backend: "auto" picks bpftrace when it is usable and falls
+    // back to perf otherwise, "bpftrace" or "perf" force one (BPFTRACE_MCP_BACKEND)
+    pub backend: String,
+    // Where the probe catalog is cached per kernel; when set, the catalog is
+    // also built in the background at startup. Disabled when set to an empty
+    // value (BPFTRACE_MCP_PROBE_CACHE_DIR)
+    pub probe_cache_dir: Option<PathBuf>,
+    // Seconds between background refreshes of the probe catalog, 0 to only
+    // build it when missing (BPFTRACE_MCP_PROBE_CACHE_REFRESH)
+    pub probe_cache_refresh_secs: u64,
+}
+
+// Private per-user directory holding spill files, journals, bundles and
+// the probe cache by default: $XDG_RUNTIME_DIR/bpftrace-mcp, or a
+// uid-suffixed directory under the temp dir when that is unset.
+pub fn runtime_dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("bpftrace-mcp"),
+        // SAFETY: geteuid(2) has no preconditions and cannot fail
+        None => std::env::temp_dir().join(format!("bpftrace-mcp-{}", unsafe { libc::geteuid() })),
+    }
+}
+
+// Create `dir` (mode 0700) if needed and check that it is a real directory
+// owned by this user that nobody else can access. Kernel trace output is
+// written here, and another local user could otherwise pre-create the
+// directory in a shared location like /tmp.
+pub fn ensure_private_dir(dir: &Path) -> io::Result<()> {
+    DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    let metadata = std::fs::symlink_metadata(dir)?;
+    // SAFETY: geteuid(2) has no preconditions and cannot fail
+    let uid = unsafe { libc::geteuid() };
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} must be a directory owned by uid {} and inaccessible to other users (mode 0700)",
+                dir.display(),
+                uid
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(default)
+}
+
+fn env_flag(name: &str) -> bool {
+    std::env::var(name)
+        .map(|value| matches!(value.trim(), "1" | "true" | "yes" | "on"))
+        .unwrap_or(false)
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        let runtime_dir = runtime_dir();
+        Self {
+            max_timeout: 60,
+            allow_long_running: false,
+            long_running_max_timeout: 4 * 3600,
+            max_long_running: 2,
+            spill_dir: runtime_dir.join("spill"),
+            max_spill_bytes: 1024 * 1024 * 1024,
+            max_program_bytes: 64 * 1024,
+            max_probes: 256,
+            max_wildcard_probes: 2000,
+            max_target_pids: 64,
+            max_attach_pids: 16,
+            discovery_timeout_secs: 30,
+            metrics_addr: None,
+            journal_dir: Some(runtime_dir.join("journal")),
+            bundle_dir: runtime_dir.join("bundles"),
+            max_bundle_bytes: 256 * 1024 * 1024,
+            admin_socket: None,
+            mcp_socket: None,
+            log_file: None,
+            backend: "auto".to_string(),
+            probe_cache_dir: Some(runtime_dir.join("probe-cache")),
+            probe_cache_refresh_secs: 6 * 3600,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            max_timeout: env_or("BPFTRACE_MCP_MAX_TIMEOUT", defaults.max_timeout).max(1),
+            allow_long_running: env_flag("BPFTRACE_MCP_ALLOW_LONG_RUNNING"),
+            long_running_max_timeout: env_or(
+                "BPFTRACE_MCP_LONG_RUNNING_MAX_TIMEOUT",
+                defaults.long_running_max_timeout,
+            )
+            .max(1),
+            max_long_running: env_or("BPFTRACE_MCP_MAX_LONG_RUNNING", defaults.max_long_running),
+            spill_dir: std::env::var_os("BPFTRACE_MCP_SPILL_DIR")
+                .map(PathBuf::from)
+                .unwrap_or(defaults.spill_dir),
+            max_spill_bytes: env_or("BPFTRACE_MCP_MAX_SPILL_BYTES", defaults.max_spill_bytes).max(1),
+            max_program_bytes: env_or("BPFTRACE_MCP_MAX_PROGRAM_BYTES", defaults.max_program_bytes),
+            max_probes: env_or("BPFTRACE_MCP_MAX_PROBES", defaults.max_probes),
+            max_wildcard_probes: env_or(
+                "BPFTRACE_MCP_MAX_WILDCARD_PROBES",
+                defaults.max_wildcard_probes,
+            ),
+            max_target_pids: env_or("BPFTRACE_MCP_MAX_TARGET_PIDS", defaults.max_target_pids),
+            max_attach_pids: env_or("BPFTRACE_MCP_MAX_ATTACH_PIDS", defaults.max_attach_pids),
+            discovery_timeout_secs: env_or(
+                "BPFTRACE_MCP_DISCOVERY_TIMEOUT",
+                defaults.discovery_timeout_secs,
+            )
+            .max(1),
+            metrics_addr: std::env::var("BPFTRACE_MCP_METRICS_ADDR").ok(),
+            journal_dir: match std::env::var_os("BPFTRACE_MCP_JOURNAL_DIR") {
+                Some(path) if path.is_empty() => None,
+                Some(path) => Some(PathBuf::from(path)),
+                None => defaults.journal_dir,
+            },
+            bundle_dir: std::env::var_os("BPFTRACE_MCP_BUNDLE_DIR")
+                .map(PathBuf::from)
+                .unwrap_or(defaults.bundle_dir),
+            max_bundle_bytes: env_or("BPFTRACE_MCP_MAX_BUNDLE_BYTES", defaults.max_bundle_bytes).max(1),
+            admin_socket: std::env::var_os("BPFTRACE_MCP_ADMIN_SOCKET").map(PathBuf::from),
+            mcp_socket: std::env::var_os("BPFTRACE_MCP_SOCKET").map(PathBuf::from),
+            log_file: std::env::var_os("BPFTRACE_MCP_LOG_FILE").map(PathBuf::from),
+            backend: std::env::var("BPFTRACE_MCP_BACKEND")
+                .map(|value| value.trim().to_ascii_lowercase())
+                .unwrap_or(defaults.backend),
+            probe_cache_dir: match std::env::var_os("BPFTRACE_MCP_PROBE_CACHE_DIR") {
+                Some(path) if path.is_empty() => None,
+                Some(path) => Some(PathBuf::from(path)),
+                None => defaults.probe_cache_dir,
+            },
+            probe_cache_refresh_secs: env_or(
+                "BPFTRACE_MCP_PROBE_CACHE_REFRESH",
+                defaults.probe_cache_refresh_secs,
+            ),
+        }
+    }
+
+    // Check every directory the server writes trace data to, including the
+    // shared runtime directory above the defaults, before anything is written
+    pub fn prepare_dirs(&self) -> io::Result<()> {
+        let runtime_dir = runtime_dir();
+        let dirs: Vec<&Path> = [Some(&self.spill_dir), self.journal_dir.as_ref(), Some(&self.bundle_dir), self.probe_cache_dir.as_ref()]
+            .into_iter()
+            .flatten()
+            .map(PathBuf::as_path)
+            .collect();
+        if dirs.iter().any(|dir| dir.starts_with(&runtime_dir)) {
+            ensure_private_dir(&runtime_dir)?;
+        }
+        for dir in dirs {
+            ensure_private_dir(dir)?;
+        }
+        Ok(())
+    }
+
+    pub fn discovery_timeout(&self) -> Duration {
+        Duration::from_secs(self.discovery_timeout_secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    #[test]
+    fn private_dir_rejects_shared_permissions() {
+        let dir = std::env::temp_dir().join(format!("bpftrace-mcp-private-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        ensure_private_dir(&dir.join("spill")).unwrap();
+        assert_eq!(fs::metadata(&dir).unwrap().permissions().mode() & 0o777, 0o700);
+
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(ensure_private_dir(&dir).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
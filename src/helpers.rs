@@ -0,0 +1,153 @@
+// Usage examples for bpftrace helpers and builtins, looked up by helper
+// name or by task keyword ("histogram", "stack", "string compare"). The
+// curated snippets live in helper_examples.json; full programs that use a
+// helper come from the bundled tool collection in tools/examples.json.
+
+use rmcp::schemars::{self, JsonSchema};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+const HELPER_EXAMPLES: &str = include_str!("helper_examples.json");
+const TOOL_EXAMPLES: &str = include_str!("tools/examples.json");
+
+// Full programs returned alongside the snippets
+const MAX_RELATED_PROGRAMS: usize = 2;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct HelperExample {
+    pub helper: String,
+    #[schemars(description = "Task keywords this example is listed under")]
+    pub tasks: Vec<String>,
+    pub snippet: String,
+    pub explanation: String,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct RelatedProgram {
+    pub description: String,
+    pub program: String,
+}
+
+#[derive(Deserialize)]
+struct ToolExamples {
+    data: Vec<ToolExample>,
+}
+
+#[derive(Deserialize)]
+struct ToolExample {
+    content: String,
+}
+
+fn helper_examples() -> &'static [HelperExample] {
+    static EXAMPLES: OnceLock<Vec<HelperExample>> = OnceLock::new();
+    EXAMPLES.get_or_init(|| serde_json::from_str(HELPER_EXAMPLES).expect("helper_examples.json is valid"))
+}
+
+// (description, program) pairs split out of "example: <description>\n```\n<program>```"
+fn tool_programs() -> &'static [RelatedProgram] {
+    static PROGRAMS: OnceLock<Vec<RelatedProgram>> = OnceLock::new();
+    PROGRAMS.get_or_init(|| {
+        let examples: ToolExamples = serde_json::from_str(TOOL_EXAMPLES).expect("tools/examples.json is valid");
+        examples
+            .data
+            .iter()
+            .filter_map(|example| {
+                let content = example.content.strip_prefix("example: ")?;
+                let (description, rest) = content.split_once('\n')?;
+                let program = rest.split("```").nth(1)?;
+                Some(RelatedProgram {
+                    description: description.trim().to_string(),
+                    program: program.trim().to_string(),
+                })
+            })
+            .collect()
+    })
+}
+
+pub fn helper_names() -> Vec<String> {
+    helper_examples().iter().map(|e| e.helper.clone()).collect()
+}
+
+pub fn task_keywords() -> Vec<String> {
+    let mut tasks: Vec<String> = helper_examples().iter().flat_map(|e| e.tasks.clone()).collect();
+    tasks.sort();
+    tasks.dedup();
+    tasks
+}
+
+// Higher is a better match: exact helper name, exact task, then partial
+// matches of the query (or each of its words) against tasks and helper
+fn score(example: &HelperExample, query: &str) -> u32 {
+    if example.helper.eq_ignore_ascii_case(query) {
+        return 4;
+    }
+    if example.tasks.iter().any(|task| task == query) {
+        return 3;
+    }
+    if example.tasks.iter().any(|task| task.contains(query)) || example.helper.contains(query) {
+        return 2;
+    }
+    let words: Vec<&str> = query.split_whitespace().filter(|word| word.len() > 2).collect();
+    let hits = words
+        .iter()
+        .filter(|word| example.tasks.iter().any(|task| task.contains(*word)))
+        .count();
+    u32::from(hits > 0 && hits * 2 >= words.len())
+}
+
+pub fn find(query: &str, limit: usize) -> Vec<HelperExample> {
+    let query = query.trim().to_ascii_lowercase();
+    let mut matches: Vec<(u32, &HelperExample)> = helper_examples()
+        .iter()
+        .map(|example| (score(example, &query), example))
+        .filter(|(score, _)| *score > 0)
+        .collect();
+    // Stable sort keeps the database's own ordering among equal scores
+    matches.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    matches.into_iter().take(limit).map(|(_, example)| example.clone()).collect()
+}
+
+// Bundled programs that call the best-matching helper, or whose
+// description mentions the query
+pub fn related_programs(query: &str, examples: &[HelperExample]) -> Vec<RelatedProgram> {
+    let query = query.trim().to_ascii_lowercase();
+    let call = examples.first().map(|example| format!("{}(", example.helper));
+    tool_programs()
+        .iter()
+        .filter(|program| {
+            call.as_ref().is_some_and(|call| program.program.contains(call.as_str()))
+                || program.description.to_ascii_lowercase().contains(&query)
+        })
+        .take(MAX_RELATED_PROGRAMS)
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Both databases are parsed lazily with expect(), so a bad edit would
+    // otherwise only show up as a panicking tool call
+    #[test]
+    fn bundled_databases_parse() {
+        let examples: Vec<HelperExample> = serde_json::from_str(HELPER_EXAMPLES).unwrap();
+        assert!(!examples.is_empty());
+        for example in &examples {
+            assert!(!example.helper.is_empty());
+            assert!(!example.tasks.is_empty(), "{} has no tasks", example.helper);
+            assert!(!example.snippet.trim().is_empty(), "{} has no snippet", example.helper);
+            assert!(!example.explanation.trim().is_empty(), "{} has no explanation", example.helper);
+        }
+        assert!(!tool_programs().is_empty());
+    }
+
+    #[test]
+    fn finds_by_helper_and_task() {
+        assert_eq!(find("hist", 5)[0].helper, "hist");
+        assert_eq!(find("KSTACK", 5)[0].helper, "kstack");
+        assert!(find("histogram", 5).iter().any(|example| example.helper == "hist"));
+        assert_eq!(find("histogram", 1).len(), 1);
+        assert!(find("no such thing", 5).is_empty());
+    }
+}
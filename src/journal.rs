@@ -0,0 +1,417 @@
+// Append-only journal of execution lifecycles (JSON lines). After a
+// crash, entries that were started but never finished identify the
+// executions that were in flight, so their processes can be reaped and
+// their IDs reported as interrupted instead of disappearing.
+//
+// Every server process writes its own `journal-<pid>.jsonl` and holds an
+// exclusive flock on it for as long as it runs. Several servers can share
+// the journal directory (one per MCP client session is common): recovery
+// only takes over journals whose lock is free, i.e. whose owner has exited.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    io::{self, Read},
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::{fs::File, io::AsyncWriteExt, sync::Mutex};
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JournalEntry {
+    Started {
+        execution_id: String,
+        time: u64,
+        timeout: u64,
+        program_hash: String,
+        spill_path: Option<PathBuf>,
+    },
+    Spawned {
+        execution_id: String,
+        child_pid: u32,
+        // Absent in journals written before start times were recorded
+        #[serde(default)]
+        start_time: Option<u64>,
+        // Command run under sudo, "bpftrace" or "perf"
+        #[serde(default = "default_tracer")]
+        tracer: String,
+    },
+    Finished {
+        execution_id: String,
+        time: u64,
+        status: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct InFlight {
+    pub execution_id: String,
+    pub time: u64,
+    pub timeout: u64,
+    pub program_hash: String,
+    pub spill_path: Option<PathBuf>,
+    pub children: Vec<SpawnedProcess>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpawnedProcess {
+    pub pid: u32,
+    pub start_time: Option<u64>,
+    pub tracer: String,
+}
+
+fn default_tracer() -> String {
+    "bpftrace".to_string()
+}
+
+// What an exited server's journal says about its executions
+#[derive(Debug, Default)]
+struct JournalState {
+    in_flight: Vec<InFlight>,
+    // Spill files of executions that finished; the server that kept them
+    // readable is gone, so nothing refers to them any more
+    finished_spills: Vec<PathBuf>,
+}
+
+// 64-bit FNV-1a, stable across builds unlike std's DefaultHasher
+pub fn program_hash(program: &str) -> String {
+    let hash = program.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+#[derive(Debug, Clone)]
+pub struct Journal {
+    path: PathBuf,
+    // The file's flock marks this process as the journal's live owner
+    file: Arc<Mutex<File>>,
+}
+
+fn is_journal(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with("journal-") && name.ends_with(".jsonl"))
+}
+
+// Non-blocking exclusive flock; false when another process holds it
+fn try_lock(file: &fs::File) -> io::Result<bool> {
+    // SAFETY: flock(2) on a descriptor owned by `file`
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let error = io::Error::last_os_error();
+    match error.raw_os_error() {
+        Some(libc::EWOULDBLOCK) => Ok(false),
+        _ => Err(error),
+    }
+}
+
+impl Journal {
+    pub async fn open(dir: &Path) -> io::Result<Self> {
+        crate::config::ensure_private_dir(dir)?;
+        // Locked under a temporary name and then renamed, so a concurrent
+        // recovery never sees the journal before its owner holds the lock
+        let pid = std::process::id();
+        let partial = dir.join(format!(".journal-{}.partial", pid));
+        let path = dir.join(format!("journal-{}.jsonl", pid));
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(&partial)?;
+        if !try_lock(&file)? {
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!("{} is locked by another process", partial.display()),
+            ));
+        }
+        fs::rename(&partial, &path)?;
+        Ok(Self {
+            path,
+            file: Arc::new(Mutex::new(File::from_std(file))),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub async fn record(&self, entry: JournalEntry) {
+        let mut line = match serde_json::to_string(&entry) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("failed to encode journal entry: {}", e);
+                return;
+            }
+        };
+        line.push('\n');
+        let mut file = self.file.lock().await;
+        let result = async {
+            file.write_all(line.as_bytes()).await?;
+            file.flush().await
+        }
+        .await;
+        if let Err(e) = result {
+            warn!("failed to write journal entry: {}", e);
+        }
+    }
+}
+
+// Executions that journals of exited servers saw start but never finish.
+// Those journals are deleted afterwards since every entry in them is then
+// accounted for, along with the spill files of their finished executions;
+// journals still locked by a running server are left alone.
+pub fn take_in_flight(dir: &Path) -> io::Result<Vec<InFlight>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut in_flight = Vec::new();
+    for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+        if !is_journal(&path) {
+            continue;
+        }
+        let mut file = OpenOptions::new().read(true).write(true).open(&path)?;
+        if !try_lock(&file)? {
+            continue;
+        }
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        let state = parse_journal(&contents);
+        for spill in &state.finished_spills {
+            match fs::remove_file(spill) {
+                Ok(()) => info!("Removed orphaned spill file {}", spill.display()),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => warn!("failed to remove orphaned spill file {}: {}", spill.display(), e),
+            }
+        }
+        in_flight.extend(state.in_flight);
+        fs::remove_file(&path)?;
+        info!("Recovered journal {}", path.display());
+    }
+    Ok(in_flight)
+}
+
+fn parse_journal(contents: &str) -> JournalState {
+    let mut in_flight: BTreeMap<String, InFlight> = BTreeMap::new();
+    let mut finished_spills = Vec::new();
+    // A torn final line from a crash mid-write is simply skipped
+    for entry in contents
+        .lines()
+        .filter_map(|line| serde_json::from_str::<JournalEntry>(line).ok())
+    {
+        match entry {
+            JournalEntry::Started {
+                execution_id,
+                time,
+                timeout,
+                program_hash,
+                spill_path,
+            } => {
+                in_flight.insert(
+                    execution_id.clone(),
+                    InFlight {
+                        execution_id,
+                        time,
+                        timeout,
+                        program_hash,
+                        spill_path,
+                        children: Vec::new(),
+                    },
+                );
+            }
+            JournalEntry::Spawned {
+                execution_id,
+                child_pid,
+                start_time,
+                tracer,
+            } => {
+                if let Some(execution) = in_flight.get_mut(&execution_id) {
+                    execution.children.push(SpawnedProcess {
+                        pid: child_pid,
+                        start_time,
+                        tracer,
+                    });
+                }
+            }
+            JournalEntry::Finished { execution_id, .. } => {
+                if let Some(execution) = in_flight.remove(&execution_id) {
+                    finished_spills.extend(execution.spill_path);
+                }
+            }
+        }
+    }
+    JournalState {
+        in_flight: in_flight.into_values().collect(),
+        finished_spills,
+    }
+}
+
+// Whether `process` is still the one that was journaled. The PID may have
+// been reused since, possibly by another server's tracer when several share
+// the journal directory, so its start time has to match as well.
+fn is_same_process(process: &SpawnedProcess) -> bool {
+    let started_then = match process.start_time {
+        Some(start_time) => crate::targeting::process_start_time(process.pid) == Some(start_time),
+        None => true,
+    };
+    started_then && crate::targeting::is_tracer_running(process.pid, &process.tracer)
+}
+
+// Terminate a leftover sudo/bpftrace or sudo/perf process if it is still
+// the journaled one
+pub fn reap(process: &SpawnedProcess) -> bool {
+    let is_ours = is_same_process(process);
+    if is_ours {
+        // SAFETY: kill(2) has no memory-safety preconditions
+        unsafe {
+            libc::kill(process.pid as libc::pid_t, libc::SIGTERM);
+        }
+    }
+    is_ours
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bpftrace-mcp-journal-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        crate::config::ensure_private_dir(&dir).unwrap();
+        dir
+    }
+
+    fn line(entry: JournalEntry) -> String {
+        serde_json::to_string(&entry).unwrap() + "\n"
+    }
+
+    fn started(execution_id: &str) -> JournalEntry {
+        JournalEntry::Started {
+            execution_id: execution_id.to_string(),
+            time: 100,
+            timeout: 10,
+            program_hash: program_hash("kprobe:vfs_read { }"),
+            spill_path: None,
+        }
+    }
+
+    #[test]
+    fn finds_started_but_unfinished_executions() {
+        let journal = [
+            line(started("exec_a")),
+            line(JournalEntry::Spawned {
+                execution_id: "exec_a".to_string(),
+                child_pid: 41,
+                start_time: Some(7),
+                tracer: "perf".to_string(),
+            }),
+            line(started("exec_b")),
+            line(JournalEntry::Finished {
+                execution_id: "exec_b".to_string(),
+                time: 110,
+                status: "completed".to_string(),
+            }),
+            // Written before start times were journaled
+            "{\"event\":\"spawned\",\"execution_id\":\"exec_a\",\"child_pid\":42}\n".to_string(),
+            // Torn write from the crash
+            "{\"event\":\"started\",\"execution_id\":\"exec_c\"".to_string(),
+        ]
+        .concat();
+
+        let in_flight = parse_journal(&journal).in_flight;
+        assert_eq!(in_flight.len(), 1);
+        assert_eq!(in_flight[0].execution_id, "exec_a");
+        assert_eq!(
+            in_flight[0].children,
+            vec![
+                SpawnedProcess { pid: 41, start_time: Some(7), tracer: "perf".to_string() },
+                SpawnedProcess { pid: 42, start_time: None, tracer: "bpftrace".to_string() },
+            ]
+        );
+    }
+
+    #[test]
+    fn removes_spill_files_of_finished_executions() {
+        let dir = test_dir("spills");
+        let spill = |execution_id: &str| {
+            let path = dir.join(format!("{}.log", execution_id));
+            fs::write(&path, "output\n").unwrap();
+            JournalEntry::Started {
+                execution_id: execution_id.to_string(),
+                time: 100,
+                timeout: 10,
+                program_hash: program_hash("kprobe:vfs_read { }"),
+                spill_path: Some(path),
+            }
+        };
+        let journal = [
+            line(spill("exec_done")),
+            line(JournalEntry::Finished {
+                execution_id: "exec_done".to_string(),
+                time: 110,
+                status: "completed".to_string(),
+            }),
+            line(spill("exec_running")),
+        ]
+        .concat();
+        fs::write(dir.join("journal-1.jsonl"), journal).unwrap();
+
+        let in_flight = take_in_flight(&dir).unwrap();
+        assert_eq!(in_flight[0].execution_id, "exec_running");
+        assert!(!dir.join("exec_done.log").exists());
+        assert!(dir.join("exec_running.log").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn does_not_reap_reused_pids() {
+        let pid = std::process::id();
+        let start_time = crate::targeting::process_start_time(pid).unwrap();
+        // The same PID with a different start time is a different process
+        assert!(!reap(&SpawnedProcess {
+            pid,
+            start_time: Some(start_time + 1),
+            tracer: "bpftrace".to_string(),
+        }));
+    }
+
+    #[test]
+    fn takes_only_journals_of_exited_servers() {
+        let dir = test_dir("owners");
+        fs::write(dir.join("journal-1.jsonl"), line(started("exec_dead"))).unwrap();
+        fs::write(dir.join("journal-2.jsonl"), line(started("exec_live"))).unwrap();
+        fs::write(dir.join("notes.txt"), line(started("exec_other"))).unwrap();
+        // A running server holds the lock on its journal
+        let live = OpenOptions::new().read(true).open(dir.join("journal-2.jsonl")).unwrap();
+        assert!(try_lock(&live).unwrap());
+
+        let in_flight = take_in_flight(&dir).unwrap();
+        assert_eq!(in_flight.len(), 1);
+        assert_eq!(in_flight[0].execution_id, "exec_dead");
+        assert!(!dir.join("journal-1.jsonl").exists());
+        assert!(dir.join("journal-2.jsonl").exists());
+
+        drop(live);
+        assert_eq!(take_in_flight(&dir).unwrap()[0].execution_id, "exec_live");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn open_journal_is_not_taken_over() {
+        let dir = test_dir("open");
+        let journal = Journal::open(&dir).await.unwrap();
+        journal.record(started("exec_running")).await;
+
+        assert!(take_in_flight(&dir).unwrap().is_empty());
+        assert!(journal.path().exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,173 @@
+// Size and complexity limits checked before a program is handed to
+// bpftrace, so pathological programs fail fast instead of compiling for
+// minutes or attaching tens of thousands of probes.
+
+use crate::{config::ServerConfig, program, McpError};
+use serde_json::json;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LimitViolation {
+    pub limit: &'static str,
+    pub actual: usize,
+    pub max: usize,
+    pub setting: &'static str,
+}
+
+impl LimitViolation {
+    pub fn into_error(self) -> McpError {
+        McpError::invalid_params(
+            format!("Program exceeds the {} limit ({} > {})", self.limit, self.actual, self.max),
+            Some(json!({
+                "kind": "validation",
+                "limit": self.limit,
+                "actual": self.actual,
+                "max": self.max,
+                "setting": self.setting
+            })),
+        )
+    }
+}
+
+pub fn check_program(source: &str, config: &ServerConfig) -> Result<(), LimitViolation> {
+    if source.len() > config.max_program_bytes {
+        return Err(LimitViolation {
+            limit: "program_bytes",
+            actual: source.len(),
+            max: config.max_program_bytes,
+            setting: "BPFTRACE_MCP_MAX_PROGRAM_BYTES",
+        });
+    }
+
+    // Probes rather than clauses, since one clause can list any number
+    let probes: usize = program::parse_clauses(source)
+        .iter()
+        .map(|clause| clause.probes.len())
+        .sum();
+    if probes > config.max_probes {
+        return Err(LimitViolation {
+            limit: "probes",
+            actual: probes,
+            max: config.max_probes,
+            setting: "BPFTRACE_MCP_MAX_PROBES",
+        });
+    }
+
+    Ok(())
+}
+
+// `matched` is the total number of probes the program's wildcards expand to;
+// each of the `instances` bpftrace processes attaches all of them
+pub fn check_wildcard_breadth(matched: usize, instances: usize, config: &ServerConfig) -> Result<(), LimitViolation> {
+    let attached = matched.saturating_mul(instances.max(1));
+    if attached > config.max_wildcard_probes {
+        return Err(LimitViolation {
+            limit: "wildcard_probes",
+            actual: attached,
+            max: config.max_wildcard_probes,
+            setting: "BPFTRACE_MCP_MAX_WILDCARD_PROBES",
+        });
+    }
+    Ok(())
+}
+
+// Every target ends up in the pid predicate or as its own instance, and a
+// broad target_comm can resolve to hundreds of processes
+pub fn check_target_pids(pids: usize, config: &ServerConfig) -> Result<(), LimitViolation> {
+    if pids > config.max_target_pids {
+        return Err(LimitViolation {
+            limit: "target_pids",
+            actual: pids,
+            max: config.max_target_pids,
+            setting: "BPFTRACE_MCP_MAX_TARGET_PIDS",
+        });
+    }
+    Ok(())
+}
+
+// Per-PID mode starts one root bpftrace per target process
+pub fn check_attach_pids(pids: usize, config: &ServerConfig) -> Result<(), LimitViolation> {
+    if pids > config.max_attach_pids {
+        return Err(LimitViolation {
+            limit: "attach_pids",
+            actual: pids,
+            max: config.max_attach_pids,
+            setting: "BPFTRACE_MCP_MAX_ATTACH_PIDS",
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> ServerConfig {
+        ServerConfig {
+            max_program_bytes: 40,
+            max_probes: 3,
+            max_wildcard_probes: 100,
+            max_target_pids: 8,
+            max_attach_pids: 4,
+            ..ServerConfig::default()
+        }
+    }
+
+    #[test]
+    fn limits_program_size() {
+        let config = config();
+        let program = format!("BEGIN {{ {} }}", "x".repeat(30));
+        assert_eq!(program.len(), 40);
+        assert!(check_program(&program, &config).is_ok());
+        let violation = check_program(&format!("{} ", program), &config).unwrap_err();
+        assert_eq!((violation.limit, violation.actual, violation.max), ("program_bytes", 41, 40));
+        assert_eq!(violation.setting, "BPFTRACE_MCP_MAX_PROGRAM_BYTES");
+    }
+
+    #[test]
+    fn counts_probes_not_clauses() {
+        let config = config();
+        assert!(check_program("kprobe:a,kprobe:b { } kprobe:c { }", &config).is_ok());
+        let violation = check_program("kprobe:a,kprobe:b,kprobe:c,kprobe:d { }", &config).unwrap_err();
+        assert_eq!((violation.limit, violation.actual, violation.max), ("probes", 4, 3));
+        assert_eq!(violation.setting, "BPFTRACE_MCP_MAX_PROBES");
+    }
+
+    #[test]
+    fn limits_wildcard_breadth_across_instances() {
+        let config = config();
+        assert!(check_wildcard_breadth(100, 1, &config).is_ok());
+        assert!(check_wildcard_breadth(25, 4, &config).is_ok());
+        // No instances yet still counts as one
+        assert!(check_wildcard_breadth(100, 0, &config).is_ok());
+        let violation = check_wildcard_breadth(101, 1, &config).unwrap_err();
+        assert_eq!((violation.limit, violation.actual), ("wildcard_probes", 101));
+        let violation = check_wildcard_breadth(26, 4, &config).unwrap_err();
+        assert_eq!((violation.actual, violation.max), (104, 100));
+        assert_eq!(violation.setting, "BPFTRACE_MCP_MAX_WILDCARD_PROBES");
+        assert_eq!(check_wildcard_breadth(usize::MAX, 2, &config).unwrap_err().actual, usize::MAX);
+    }
+
+    #[test]
+    fn limits_target_and_attach_pids() {
+        let config = config();
+        assert!(check_target_pids(8, &config).is_ok());
+        let violation = check_target_pids(9, &config).unwrap_err();
+        assert_eq!((violation.limit, violation.setting), ("target_pids", "BPFTRACE_MCP_MAX_TARGET_PIDS"));
+
+        assert!(check_attach_pids(4, &config).is_ok());
+        let violation = check_attach_pids(5, &config).unwrap_err();
+        assert_eq!((violation.limit, violation.setting), ("attach_pids", "BPFTRACE_MCP_MAX_ATTACH_PIDS"));
+    }
+
+    #[test]
+    fn violation_becomes_validation_error() {
+        let error = check_attach_pids(5, &config()).unwrap_err().into_error();
+        let data = error.data.unwrap();
+        assert_eq!(data["kind"], "validation");
+        assert_eq!(data["limit"], "attach_pids");
+        assert_eq!(data["actual"], 5);
+        assert_eq!(data["max"], 4);
+        assert_eq!(data["setting"], "BPFTRACE_MCP_MAX_ATTACH_PIDS");
+        assert!(error.message.contains("(5 > 4)"));
+    }
+}
@@ -0,0 +1,66 @@
+// Log file that tracing writes to when BPFTRACE_MCP_LOG_FILE is set, with
+// rotation on demand from the admin socket.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+#[derive(Debug)]
+pub struct LogFile {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+impl LogFile {
+    pub fn open(path: PathBuf) -> io::Result<Arc<Self>> {
+        let file = open_append(&path)?;
+        Ok(Arc::new(Self {
+            path,
+            file: Mutex::new(file),
+        }))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Move the current log to `<path>.1` and continue in a fresh file
+    pub fn rotate(&self) -> io::Result<PathBuf> {
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        file.flush()?;
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(".1");
+        let rotated = PathBuf::from(rotated);
+        fs::rename(&self.path, &rotated)?;
+        *file = open_append(&self.path)?;
+        Ok(rotated)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LogWriter(pub Arc<LogFile>);
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .file
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0
+            .file
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .flush()
+    }
+}
@@ -1,43 +1,100 @@
+mod admin;
+mod attach;
+mod bundle;
+mod config;
+mod helpers;
+mod journal;
+mod limits;
+mod logfile;
+mod metrics;
+mod output;
+mod perf;
+mod probe_cache;
+mod program;
+mod resources;
+mod responses;
+mod spill;
+mod summary;
+mod targeting;
+mod transport;
+
 use anyhow::Result;
+use config::ServerConfig;
 use dashmap::DashMap;
 use rmcp::{
     handler::server::{router::tool::ToolRouter, tool::Parameters},
     model::*,
-    schemars, tool, tool_handler, tool_router,
+    schemars,
+    service::RequestContext,
+    tool, tool_handler, tool_router,
     transport::stdio,
-    ServerHandler, ServiceExt,
+    RoleServer, ServerHandler, ServiceExt,
 };
 use serde::Deserialize;
 use serde_json::json;
 use std::{
+    collections::BTreeMap,
     future::Future,
     process::Stdio,
     sync::Arc,
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use tokio::{
     io::{AsyncBufReadExt, BufReader},
-    process::Command,
-    sync::Mutex,
+    process::{Child, Command},
+    sync::{Mutex, Semaphore},
+    task::JoinSet,
     time::sleep,
 };
+use tokio_util::sync::CancellationToken;
 use tracing::info;
 use uuid::Uuid;
 
 type McpError = rmcp::model::ErrorData;
 
+// Deadline for the unfiltered `bpftrace -l` that builds the probe cache
+const PROBE_CACHE_FETCH_TIMEOUT: Duration = Duration::from_secs(300);
+
+// Wildcards expanded at once when checking a program
+const MAX_CONCURRENT_EXPANSIONS: usize = 4;
+
+// (map, key, attached pid) of a value published on the metrics endpoint
+type MapValueKey = (String, String, Option<u32>);
+
 #[derive(Debug, Clone)]
 struct ExecutionBuffer {
     lines: Arc<Mutex<Vec<String>>>,
     status: Arc<Mutex<String>>,
     max_lines: usize,
     creation_time: u64,
+    started_at: Instant,
+    timeout: Duration,
     completion_time: Arc<Mutex<Option<u64>>>,
     error_message: Arc<Mutex<Option<String>>>,
+    lost_events: Arc<Mutex<u64>>,
+    export_metrics: bool,
+    map_values: Arc<Mutex<BTreeMap<MapValueKey, f64>>>,
+    // Map whose text-mode print is in progress, per attached pid
+    map_prints: Arc<Mutex<BTreeMap<Option<u32>, String>>>,
+    long_running: bool,
+    spill: Option<spill::SpillFile>,
+    program: String,
+    target_pids: Vec<u32>,
+    per_pid_attach: bool,
+    attach_report: Arc<Mutex<attach::AttachReport>>,
+    // PIDs of the sudo processes currently running for this execution
+    child_pids: Arc<Mutex<Vec<u32>>>,
+    // Command those sudo processes run, "bpftrace" or "perf"
+    tracer: &'static str,
+    cancel: CancellationToken,
+    // Events received in each second since the execution started
+    event_rate: Arc<Mutex<Vec<u64>>>,
+    // Set for executions imported from a bundle; these are read-only
+    replay: Option<Arc<bundle::ReplayInfo>>,
 }
 
 impl ExecutionBuffer {
-    fn new(max_lines: usize) -> Self {
+    fn new(max_lines: usize, timeout: Duration) -> Self {
         Self {
             lines: Arc::new(Mutex::new(Vec::new())),
             status: Arc::new(Mutex::new("running".to_string())),
@@ -46,12 +103,42 @@ impl ExecutionBuffer {
                 .duration_since(UNIX_EPOCH)
                 .unwrap()
                 .as_secs(),
+            started_at: Instant::now(),
+            timeout,
             completion_time: Arc::new(Mutex::new(None)),
             error_message: Arc::new(Mutex::new(None)),
+            lost_events: Arc::new(Mutex::new(0)),
+            export_metrics: false,
+            map_values: Arc::new(Mutex::new(BTreeMap::new())),
+            map_prints: Arc::new(Mutex::new(BTreeMap::new())),
+            long_running: false,
+            spill: None,
+            program: String::new(),
+            target_pids: Vec::new(),
+            per_pid_attach: false,
+            attach_report: Arc::new(Mutex::new(attach::AttachReport::default())),
+            child_pids: Arc::new(Mutex::new(Vec::new())),
+            tracer: "bpftrace",
+            cancel: CancellationToken::new(),
+            event_rate: Arc::new(Mutex::new(Vec::new())),
+            replay: None,
         }
     }
 
     async fn add_line(&self, line: String) {
+        if summary::is_event(&line) {
+            let second = self.started_at.elapsed().as_secs() as usize;
+            let mut event_rate = self.event_rate.lock().await;
+            if event_rate.len() <= second {
+                event_rate.resize(second + 1, 0);
+            }
+            event_rate[second] += 1;
+        }
+        if let Some(spill) = &self.spill {
+            if let Err(e) = spill.append(&line).await {
+                tracing::warn!("failed to spill output to {}: {}", spill.path().display(), e);
+            }
+        }
         let mut lines = self.lines.lock().await;
         if lines.len() < self.max_lines {
             lines.push(line);
@@ -60,6 +147,79 @@ impl ExecutionBuffer {
         }
     }
 
+    // (total lines, lines in range). Long-running executions page through
+    // the spilled output so nothing is lost to the in-memory line cap.
+    async fn read_lines(&self, offset: usize, limit: usize) -> std::io::Result<(usize, Vec<String>)> {
+        match &self.spill {
+            Some(spill) => {
+                let total_lines = spill.line_count().await;
+                Ok((total_lines, spill.read_range(offset, limit).await?))
+            }
+            None => {
+                let lines = self.lines.lock().await;
+                let start_index = offset.min(lines.len());
+                let end_index = offset.saturating_add(limit).min(lines.len());
+                Ok((lines.len(), lines[start_index..end_index].to_vec()))
+            }
+        }
+    }
+
+    // Every output line in order, read from the spill file when there is one
+    async fn output_lines(&self) -> std::io::Result<OutputLines> {
+        Ok(match &self.spill {
+            Some(spill) => OutputLines::Spill(spill.lines().await?),
+            None => OutputLines::Memory(self.lines.lock().await.clone().into_iter()),
+        })
+    }
+
+    async fn line_count(&self) -> usize {
+        match &self.spill {
+            Some(spill) => spill.line_count().await,
+            None => self.lines.lock().await.len(),
+        }
+    }
+
+    async fn add_lost_events(&self, count: u64) {
+        *self.lost_events.lock().await += count;
+    }
+
+    // Every print of a map is a full snapshot, so once a new print of a map
+    // starts its previous entries are dropped; keys removed with clear() or
+    // delete() then stop being exported. A text-mode print is the run of
+    // consecutive lines of one map, a JSON-mode print a single line.
+    async fn record_map_samples(&self, line: &str, pid: Option<u32>) {
+        let samples = output::parse_map_samples(line);
+        let json = line.trim_start().starts_with('{');
+        let mut map_prints = self.map_prints.lock().await;
+        if samples.is_empty() || json {
+            map_prints.remove(&pid);
+        }
+        if samples.is_empty() {
+            return;
+        }
+        let mut map_values = self.map_values.lock().await;
+        for sample in samples {
+            if map_prints.get(&pid) != Some(&sample.map) {
+                map_values.retain(|(map, _, entry_pid), _| *map != sample.map || *entry_pid != pid);
+                map_prints.insert(pid, sample.map.clone());
+            }
+            map_values.insert((sample.map, sample.key, pid), sample.value);
+        }
+        if json {
+            map_prints.remove(&pid);
+        }
+    }
+
+    // (progress_percent, time_remaining_secs) measured against the timeout
+    async fn progress(&self) -> (u64, u64) {
+        if *self.status.lock().await != "running" {
+            return (100, 0);
+        }
+        let elapsed = self.started_at.elapsed();
+        let percent = (elapsed.as_secs_f64() / self.timeout.as_secs_f64().max(1.0) * 100.0).min(100.0);
+        (percent as u64, self.timeout.saturating_sub(elapsed).as_secs())
+    }
+
     async fn mark_completed(&self) {
         *self.status.lock().await = "completed".to_string();
         *self.completion_time.lock().await = Some(
@@ -82,11 +242,36 @@ impl ExecutionBuffer {
     }
 }
 
+enum OutputLines {
+    Memory(std::vec::IntoIter<String>),
+    Spill(tokio::io::Lines<BufReader<tokio::fs::File>>),
+}
+
+impl OutputLines {
+    async fn next_line(&mut self) -> std::io::Result<Option<String>> {
+        match self {
+            OutputLines::Memory(lines) => Ok(lines.next()),
+            OutputLines::Spill(lines) => lines.next_line().await,
+        }
+    }
+}
+
 #[derive(Clone)]
 struct BpftraceServer {
     tool_router: ToolRouter<Self>,
     sudo_password: Arc<String>,
     execution_buffers: Arc<DashMap<String, ExecutionBuffer>>,
+    config: Arc<ServerConfig>,
+    // MCP session this handle serves: 0 for stdio, then one per socket connection
+    session: u64,
+    // Output resource URIs each session is subscribed to
+    subscriptions: Arc<DashMap<(u64, String), ()>>,
+    started_at: Instant,
+    journal: Option<journal::Journal>,
+    backend: perf::Backend,
+    probe_cache: Option<probe_cache::ProbeCache>,
+    // One permit per long-running execution allowed to run at once
+    long_running_slots: Arc<Semaphore>,
 }
 
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
@@ -101,9 +286,21 @@ struct ListProbesRequest {
 struct ExecProgramRequest {
     #[schemars(description = "The bpftrace program to execute")]
     program: String,
-    #[schemars(description = "Execution timeout in seconds (default: 10, max: 60)")]
+    #[schemars(description = "Execution timeout in seconds (default: 10, max: 60 unless raised by the operator)")]
     #[serde(default = "default_timeout")]
     timeout: u64,
+    #[schemars(description = "Optional process name (comm) to trace; matching PIDs are resolved at start and the program is restricted to them")]
+    #[serde(default)]
+    target_comm: Option<String>,
+    #[schemars(description = "Optional list of PIDs to trace; combined with any PIDs resolved from target_comm. Programs using USDT or library uprobes get one bpftrace instance per PID (at most 16 unless raised by the operator) with output lines tagged '[pid N]'; other programs are filtered with a pid predicate and their output is not tagged")]
+    #[serde(default)]
+    pids: Vec<u32>,
+    #[schemars(description = "Publish scalar map entries printed by the program (e.g. from an interval probe) as Prometheus gauges on the metrics endpoint")]
+    #[serde(default)]
+    export_metrics: bool,
+    #[schemars(description = "Allow a multi-hour timeout for capturing rare events (must be enabled by the operator); full output is spilled to disk and read back in pages with get_result")]
+    #[serde(default)]
+    long_running: bool,
 }
 
 fn default_timeout() -> u64 {
@@ -127,19 +324,529 @@ fn default_limit() -> usize {
     1000
 }
 
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+struct PerfTraceRequest {
+    #[schemars(description = "What to collect: 'syscalls' for per-syscall counts and latencies (perf trace -s) or 'cpu_sample' for sampled call stacks (perf record -g)")]
+    mode: perf::PerfMode,
+    #[schemars(description = "How long to collect for, in seconds (default: 10, max: 60 unless raised by the operator)")]
+    #[serde(default = "default_timeout")]
+    duration: u64,
+    #[schemars(description = "Optional process name (comm) to restrict collection to")]
+    #[serde(default)]
+    target_comm: Option<String>,
+    #[schemars(description = "Optional list of PIDs to restrict collection to; combined with any PIDs resolved from target_comm")]
+    #[serde(default)]
+    pids: Vec<u32>,
+    #[schemars(description = "Sampling frequency in Hz for cpu_sample (default: 99)")]
+    #[serde(default = "default_frequency")]
+    frequency: u32,
+}
+
+fn default_frequency() -> u32 {
+    99
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+struct SummarizeExecutionRequest {
+    #[schemars(description = "The execution ID returned by exec_program")]
+    execution_id: String,
+    #[schemars(description = "Field to rank keys by: a 0-based whitespace column, or a name matched as name=value, 'name: value' or a JSON key (default: the whole line)")]
+    #[serde(default)]
+    key_field: Option<String>,
+    #[schemars(description = "How many of the most frequent keys to return (default: 10)")]
+    #[serde(default = "default_top_n")]
+    top_n: usize,
+    #[schemars(description = "Numeric field to compute min/max/avg over, using the same selector syntax as key_field")]
+    #[serde(default)]
+    value_field: Option<String>,
+    #[schemars(description = "Width in seconds of each events-per-second bucket (default: chosen to give at most 60 buckets)")]
+    #[serde(default)]
+    bucket_secs: Option<u64>,
+}
+
+fn default_top_n() -> usize {
+    10
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+struct GetHelperExamplesRequest {
+    #[schemars(description = "A helper or builtin name (e.g. 'hist', 'kstack', 'str') or a task keyword (e.g. 'histogram', 'stack', 'string compare', 'latency')")]
+    query: String,
+    #[schemars(description = "Maximum snippets to return (default: 5)")]
+    #[serde(default = "default_example_limit")]
+    limit: usize,
+}
+
+fn default_example_limit() -> usize {
+    5
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+struct ExportBundleRequest {
+    #[schemars(description = "The execution ID to export")]
+    execution_id: String,
+    #[schemars(description = "File name for the bundle inside the server's bundle directory (default: <execution_id>.bundle.json)")]
+    #[serde(default)]
+    file_name: Option<String>,
+    #[schemars(description = "Include `bpftrace --info` output in the system snapshot (default: true)")]
+    #[serde(default = "default_true")]
+    include_bpftrace_info: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+struct ImportBundleRequest {
+    #[schemars(description = "File name of a bundle written by export_bundle, inside the server's bundle directory")]
+    file_name: String,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+struct GetExecutionInfoRequest {
+    #[schemars(description = "The execution ID returned by exec_program")]
+    execution_id: String,
+}
+
 
 impl BpftraceServer {
     async fn run_bpftrace_program(
-        _execution_id: String,
+        execution_id: String,
         program: String,
         timeout: Duration,
         sudo_password: String,
         buffer: ExecutionBuffer,
+        attach_pids: Vec<u32>,
+        journal: Option<journal::Journal>,
     ) {
+        if attach_pids.is_empty() {
+            Self::run_bpftrace_instance(
+                execution_id.clone(),
+                program,
+                None,
+                timeout,
+                sudo_password,
+                buffer.clone(),
+                journal.clone(),
+            )
+            .await;
+        } else {
+            // One `bpftrace -p` per process, all feeding the same buffer
+            let instances: Vec<_> = attach_pids
+                .into_iter()
+                .map(|pid| {
+                    let program =
+                        program::add_predicate(&program, &targeting::pid_predicate(&[pid]));
+                    tokio::spawn(Self::run_bpftrace_instance(
+                        execution_id.clone(),
+                        program,
+                        Some(pid),
+                        timeout,
+                        sudo_password.clone(),
+                        buffer.clone(),
+                        journal.clone(),
+                    ))
+                })
+                .collect();
+            for instance in instances {
+                let _ = instance.await;
+            }
+        }
+
+        let status = buffer.status.lock().await.clone();
+        if status == "running" {
+            buffer.mark_completed().await;
+        }
+
+        if let Some(journal) = &journal {
+            let status = buffer.status.lock().await.clone();
+            journal
+                .record(journal::JournalEntry::Finished {
+                    execution_id,
+                    time: buffer.completion_time.lock().await.unwrap_or_default(),
+                    status,
+                })
+                .await;
+        }
+    }
+
+    // Handle for another MCP session; all state except the session's own
+    // subscriptions is shared with every other session
+    fn for_session(&self, session: u64) -> Self {
+        Self {
+            session,
+            ..self.clone()
+        }
+    }
+
+    // Explicit PIDs plus those matching `target_comm`, all of which must exist
+    fn resolve_targets(&self, pids: Vec<u32>, target_comm: Option<&str>) -> Result<Vec<u32>, McpError> {
+        let missing = targeting::missing_pids(&pids);
+        if !missing.is_empty() {
+            return Err(McpError::invalid_params(
+                "Some requested PIDs do not exist",
+                Some(json!({"missing_pids": missing})),
+            ));
+        }
+        let mut target_pids = pids;
+        if let Some(comm) = target_comm {
+            let comm_pids = targeting::find_pids_by_comm(comm);
+            if comm_pids.is_empty() {
+                return Err(McpError::invalid_params(
+                    "No running process matches target_comm",
+                    Some(json!({"target_comm": comm})),
+                ));
+            }
+            target_pids.extend(comm_pids);
+        }
+        target_pids.sort_unstable();
+        target_pids.dedup();
+        limits::check_target_pids(target_pids.len(), &self.config).map_err(limits::LimitViolation::into_error)?;
+        Ok(target_pids)
+    }
+
+    // Send SIGTERM first so sudo can relay it and bpftrace detaches its
+    // probes cleanly; fall back to SIGKILL if the process does not exit.
+    async fn terminate_child(child: &mut Child) {
+        if let Some(pid) = child.id() {
+            // SAFETY: kill(2) has no memory-safety preconditions
+            unsafe {
+                libc::kill(pid as libc::pid_t, libc::SIGTERM);
+            }
+            if tokio::time::timeout(Duration::from_secs(2), child.wait()).await.is_ok() {
+                return;
+            }
+        }
+        let _ = child.kill().await;
+    }
+
+    // Run a short-lived bpftrace discovery command (e.g. `-l`, `--info`)
+    // and collect its output, killing it if it exceeds `timeout`.
+    async fn run_discovery_command(
+        sudo_password: &str,
+        args: &[String],
+        timeout: Duration,
+    ) -> Result<std::process::Output, McpError> {
         let mut cmd = Command::new("sudo");
         cmd.arg("-S")
             .arg("bpftrace")
-            .arg("-e")
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        let mut child = match cmd.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                return Err(McpError::internal_error(
+                    "Failed to spawn bpftrace process",
+                    Some(json!({"kind": "spawn_failed", "error": e.to_string()})),
+                ));
+            }
+        };
+
+        // Send password to sudo
+        if let Some(mut stdin) = child.stdin.take() {
+            use tokio::io::AsyncWriteExt;
+            let _ = stdin
+                .write_all(format!("{}\n", sudo_password).as_bytes())
+                .await;
+            let _ = stdin.flush().await;
+        }
+
+        let mut stdout = child.stdout.take().unwrap();
+        let mut stderr = child.stderr.take().unwrap();
+        let result = tokio::time::timeout(timeout, async {
+            use tokio::io::AsyncReadExt;
+            let mut stdout_bytes = Vec::new();
+            let mut stderr_bytes = Vec::new();
+            let (stdout_read, stderr_read) = tokio::join!(
+                stdout.read_to_end(&mut stdout_bytes),
+                stderr.read_to_end(&mut stderr_bytes)
+            );
+            stdout_read?;
+            stderr_read?;
+            let status = child.wait().await?;
+            Ok::<_, std::io::Error>(std::process::Output {
+                status,
+                stdout: stdout_bytes,
+                stderr: stderr_bytes,
+            })
+        })
+        .await;
+
+        let output = match result {
+            Ok(Ok(output)) => output,
+            Ok(Err(e)) => {
+                return Err(McpError::internal_error(
+                    "Failed to execute bpftrace",
+                    Some(json!({"kind": "io_error", "error": e.to_string()})),
+                ));
+            }
+            Err(_) => {
+                Self::terminate_child(&mut child).await;
+                return Err(McpError::internal_error(
+                    "Bpftrace command timed out",
+                    Some(json!({
+                        "kind": "timeout",
+                        "timeout_secs": timeout.as_secs(),
+                        "command": format!("bpftrace {}", args.join(" "))
+                    })),
+                ));
+            }
+        };
+
+        if !output.status.success() {
+            return Err(McpError::internal_error(
+                "Bpftrace command failed",
+                Some(json!({
+                    "kind": "command_failed",
+                    "stderr": String::from_utf8_lossy(&output.stderr).to_string()
+                })),
+            ));
+        }
+
+        Ok(output)
+    }
+
+    // Probe names matching `filter`, from the probe cache when it can answer
+    async fn list_probe_names(&self, filter: Option<String>) -> Result<Vec<String>, McpError> {
+        if let Some(cache) = &self.probe_cache {
+            if let Some(probes) = cache.lookup(filter.as_deref()).await {
+                return Ok(probes);
+            }
+        }
+
+        let probes = Self::fetch_probe_names(
+            &self.sudo_password,
+            filter.clone(),
+            None,
+            self.config.discovery_timeout(),
+        )
+        .await?;
+        if let (Some(cache), None) = (&self.probe_cache, &filter) {
+            if let Err(e) = cache.store(probes.clone()).await {
+                tracing::warn!("failed to write probe cache: {}", e);
+            }
+        }
+        Ok(probes)
+    }
+
+    // `pid` lists the probes of that process, which is how library uprobes
+    // and USDT probes are resolved
+    async fn fetch_probe_names(
+        sudo_password: &str,
+        filter: Option<String>,
+        pid: Option<u32>,
+        timeout: Duration,
+    ) -> Result<Vec<String>, McpError> {
+        let mut args = vec!["-l".to_string()];
+        if let Some(pid) = pid {
+            args.extend(["-p".to_string(), pid.to_string()]);
+        }
+        args.extend(filter);
+        let output = Self::run_discovery_command(sudo_password, &args, timeout).await?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let probes: Vec<String> = stdout
+            .lines()
+            .filter(|line| !line.is_empty() && !line.starts_with("[sudo]"))
+            .map(|s| s.to_string())
+            .collect();
+
+        Ok(probes)
+    }
+
+    // Expand every wildcard in the report, concurrently and within one
+    // discovery deadline overall. A wildcard that cannot be expanded fails
+    // the request: its breadth is unknown, so the limit cannot be enforced.
+    async fn expand_wildcards(
+        &self,
+        attach_report: &mut attach::AttachReport,
+        target_pids: &[u32],
+    ) -> Result<(), McpError> {
+        let slots = Arc::new(Semaphore::new(MAX_CONCURRENT_EXPANSIONS));
+        let mut expansions = JoinSet::new();
+        for probe in attach_report.wildcards() {
+            let server = self.clone();
+            let slots = slots.clone();
+            // Library uprobes and USDT probes only resolve against a process
+            let pid = target_pids
+                .first()
+                .copied()
+                .filter(|_| program::needs_process_context(&probe));
+            expansions.spawn(async move {
+                let _slot = slots.acquire_owned().await;
+                let matches = match pid {
+                    Some(pid) => {
+                        Self::fetch_probe_names(
+                            &server.sudo_password,
+                            Some(probe.clone()),
+                            Some(pid),
+                            server.config.discovery_timeout(),
+                        )
+                        .await
+                    }
+                    None => server.list_probe_names(Some(probe.clone())).await,
+                };
+                (probe, matches)
+            });
+        }
+
+        let mut unresolved = Vec::new();
+        let deadline = tokio::time::Instant::now() + self.config.discovery_timeout();
+        loop {
+            match tokio::time::timeout_at(deadline, expansions.join_next()).await {
+                Ok(Some(Ok((probe, Ok(matches))))) => attach_report.set_matches(&probe, matches),
+                Ok(Some(Ok((probe, Err(e))))) => {
+                    unresolved.push(json!({"probe": probe, "error": e.message, "details": e.data}));
+                }
+                Ok(Some(Err(e))) => unresolved.push(json!({"error": e.to_string()})),
+                Ok(None) => break,
+                Err(_) => {
+                    expansions.abort_all();
+                    unresolved.extend(
+                        attach_report
+                            .requested
+                            .iter()
+                            .filter(|probe| probe.wildcard && probe.matched.is_none())
+                            .map(|probe| json!({"probe": probe.probe, "error": "Expansion timed out"})),
+                    );
+                    break;
+                }
+            }
+        }
+
+        if !unresolved.is_empty() {
+            return Err(McpError::invalid_params(
+                "Wildcard probes could not be expanded, so the wildcard limit cannot be checked",
+                Some(json!({
+                    "kind": "unresolved_wildcard",
+                    "unresolved": unresolved,
+                    "timeout_secs": self.config.discovery_timeout_secs,
+                    "hint": "Library uprobe and USDT wildcards need target_comm or pids; otherwise name the probes explicitly"
+                })),
+            ));
+        }
+        attach_report.wildcards_resolved = true;
+        Ok(())
+    }
+
+    // Load the probe catalog for the running kernel from disk, then build
+    // it in the background if it is missing or stale and keep refreshing
+    // it on the configured schedule.
+    fn start_probe_cache(&self) {
+        let Some(cache) = self.probe_cache.clone() else {
+            return;
+        };
+        let sudo_password = self.sudo_password.clone();
+        let refresh = Duration::from_secs(self.config.probe_cache_refresh_secs);
+
+        tokio::spawn(async move {
+            if let Some(fetched_at) = cache.load().await {
+                info!("Loaded probe cache built at {}", fetched_at);
+            }
+            loop {
+                let stale = match cache.age().await {
+                    Some(age) => !refresh.is_zero() && age >= refresh.as_secs(),
+                    None => true,
+                };
+                if stale {
+                    // A full listing is much slower than a filtered one
+                    match Self::fetch_probe_names(&sudo_password, None, None, PROBE_CACHE_FETCH_TIMEOUT).await {
+                        Ok(probes) => match cache.store(probes).await {
+                            Ok(path) => info!("Refreshed probe cache at {}", path.display()),
+                            Err(e) => tracing::warn!("failed to write probe cache: {}", e),
+                        },
+                        Err(e) => tracing::warn!("failed to build probe cache: {}", e.message),
+                    }
+                }
+                // Re-check at least hourly, even with refresh disabled, so a
+                // catalog that failed to build is retried
+                let recheck = Duration::from_secs(3600);
+                sleep(if refresh.is_zero() { recheck } else { refresh.min(recheck) }).await;
+            }
+        });
+    }
+
+    // Reap processes left behind by executions that were running when a
+    // previous server process died, and keep their IDs around as
+    // interrupted so clients polling them get a definite answer.
+    async fn recover_interrupted(&self, in_flight: Vec<journal::InFlight>) {
+        for execution in in_flight {
+            for process in &execution.children {
+                if journal::reap(process) {
+                    info!("Terminated leftover process {} of {}", process.pid, execution.execution_id);
+                }
+            }
+
+            let mut buffer = ExecutionBuffer::new(10000, Duration::from_secs(execution.timeout));
+            buffer.creation_time = execution.time;
+            if let Some(path) = execution.spill_path {
+                buffer.spill = spill::SpillFile::open_existing(path).await.ok();
+            }
+            buffer
+                .mark_failed(format!(
+                    "Interrupted: the server stopped while this execution was running (program hash {})",
+                    execution.program_hash
+                ))
+                .await;
+            *buffer.status.lock().await = "interrupted".to_string();
+
+            info!("Marked {} as interrupted", execution.execution_id);
+            self.execution_buffers.insert(execution.execution_id, buffer);
+        }
+    }
+
+    async fn remove_execution(buffers: &DashMap<String, ExecutionBuffer>, execution_id: &str) {
+        if let Some((_, buffer)) = buffers.remove(execution_id) {
+            if let Some(spill) = &buffer.spill {
+                spill.remove().await;
+            }
+        }
+    }
+
+    // Bundles are only ever written to and read from the bundle directory
+    fn bundle_path(&self, file_name: String) -> Result<std::path::PathBuf, McpError> {
+        if file_name.is_empty() || file_name.contains('/') || file_name.starts_with('.') {
+            return Err(McpError::invalid_params(
+                "file_name must be a plain file name",
+                Some(json!({"file_name": file_name})),
+            ));
+        }
+        Ok(self.config.bundle_dir.join(file_name))
+    }
+
+    async fn handle_stderr_line(buffer: &ExecutionBuffer, tag: &str, line: String) {
+        if let Some(lost) = output::parse_lost_events(&line) {
+            buffer.add_lost_events(lost).await;
+        }
+        if !line.starts_with("[sudo] password") {
+            buffer.add_line(format!("{}[Error] {}", tag, line)).await;
+        }
+    }
+
+    async fn run_bpftrace_instance(
+        execution_id: String,
+        program: String,
+        attach_pid: Option<u32>,
+        timeout: Duration,
+        sudo_password: String,
+        buffer: ExecutionBuffer,
+        journal: Option<journal::Journal>,
+    ) {
+        let tag = attach_pid
+            .map(|pid| format!("[pid {}] ", pid))
+            .unwrap_or_default();
+
+        let mut cmd = Command::new("sudo");
+        cmd.arg("-S").arg("bpftrace");
+        if let Some(pid) = attach_pid {
+            cmd.arg("-p").arg(pid.to_string());
+        }
+        cmd.arg("-e")
             .arg(&program)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
@@ -155,6 +862,21 @@ impl BpftraceServer {
             }
         };
 
+        let child_pid = child.id();
+        if let Some(pid) = child_pid {
+            buffer.child_pids.lock().await.push(pid);
+            if let Some(journal) = &journal {
+                journal
+                    .record(journal::JournalEntry::Spawned {
+                        execution_id,
+                        child_pid: pid,
+                        start_time: targeting::process_start_time(pid),
+                        tracer: buffer.tracer.to_string(),
+                    })
+                    .await;
+            }
+        }
+
         // Send password to sudo
         if let Some(mut stdin) = child.stdin.take() {
             use tokio::io::AsyncWriteExt;
@@ -174,16 +896,31 @@ impl BpftraceServer {
             tokio::select! {
                 _ = sleep(Duration::from_millis(100)) => {
                     if start_time.elapsed() > timeout {
-                        let _ = child.kill().await;
-                        buffer.add_line("[Execution timed out]".to_string()).await;
+                        Self::terminate_child(&mut child).await;
+                        buffer.add_line(format!("{}[Execution timed out]", tag)).await;
                         buffer.mark_failed("Timeout".to_string()).await;
                         break;
                     }
                 }
+                _ = buffer.cancel.cancelled() => {
+                    Self::terminate_child(&mut child).await;
+                    buffer.add_line(format!("{}[Execution cancelled]", tag)).await;
+                    buffer.mark_failed("Cancelled".to_string()).await;
+                    break;
+                }
                 line = stdout_reader.next_line() => {
                     match line {
                         Ok(Some(line)) => {
-                            buffer.add_line(line).await;
+                            if let Some(lost) = output::parse_lost_events(&line) {
+                                buffer.add_lost_events(lost).await;
+                            }
+                            if let Some(attached) = output::parse_attached_probes(&line) {
+                                buffer.attach_report.lock().await.add_attached(attached);
+                            }
+                            if buffer.export_metrics {
+                                buffer.record_map_samples(&line, attach_pid).await;
+                            }
+                            buffer.add_line(format!("{}{}", tag, line)).await;
                         }
                         Ok(None) => break,
                         Err(e) => {
@@ -194,11 +931,7 @@ impl BpftraceServer {
                 }
                 line = stderr_reader.next_line() => {
                     match line {
-                        Ok(Some(line)) => {
-                            if !line.starts_with("[sudo] password") {
-                                buffer.add_line(format!("[Error] {}", line)).await;
-                            }
-                        }
+                        Ok(Some(line)) => Self::handle_stderr_line(&buffer, &tag, line).await,
                         Ok(None) => {}
                         Err(_) => {}
                     }
@@ -206,22 +939,50 @@ impl BpftraceServer {
             }
         }
 
-        let _ = child.wait().await;
+        // bpftrace reports some warnings (e.g. lost events) on stderr while
+        // exiting, after stdout has already closed
+        while let Ok(Ok(Some(line))) =
+            tokio::time::timeout(Duration::from_millis(500), stderr_reader.next_line()).await
+        {
+            Self::handle_stderr_line(&buffer, &tag, line).await;
+        }
 
-        let status = buffer.status.lock().await.clone();
-        if status == "running" {
-            buffer.mark_completed().await;
+        let _ = child.wait().await;
+        if let Some(pid) = child_pid {
+            buffer.child_pids.lock().await.retain(|p| *p != pid);
         }
     }
 }
 
 #[tool_router]
 impl BpftraceServer {
-    fn new(sudo_password: String) -> Self {
+    fn new(sudo_password: String, config: ServerConfig, journal: Option<journal::Journal>) -> Self {
+        let backend = perf::detect_backend(&config.backend);
+        if backend == perf::Backend::Perf {
+            info!("bpftrace is unavailable or disabled; running in degraded mode with perf");
+        }
+        let config_probe_cache = config
+            .probe_cache_dir
+            .clone()
+            .filter(|_| backend == perf::Backend::Bpftrace)
+            .map(probe_cache::ProbeCache::new);
+        let config_max_long_running = config.max_long_running;
+        let mut tool_router = Self::tool_router();
+        perf::adapt_tools(&mut tool_router, backend);
+        responses::advertise(&mut tool_router);
+
         let server = Self {
-            tool_router: Self::tool_router(),
+            tool_router,
             sudo_password: Arc::new(sudo_password),
             execution_buffers: Arc::new(DashMap::new()),
+            config: Arc::new(config),
+            session: 0,
+            subscriptions: Arc::new(DashMap::new()),
+            started_at: Instant::now(),
+            journal,
+            backend,
+            probe_cache: config_probe_cache,
+            long_running_slots: Arc::new(Semaphore::new(config_max_long_running)),
         };
 
         // Start cleanup task
@@ -237,15 +998,24 @@ impl BpftraceServer {
                     .unwrap()
                     .as_secs();
 
-                let mut to_remove = Vec::new();
-                for entry in buffers.iter() {
-                    if current_time - entry.value().creation_time > max_age {
-                        to_remove.push(entry.key().clone());
-                    }
-                }
+                let entries: Vec<(String, ExecutionBuffer)> = buffers
+                    .iter()
+                    .map(|entry| (entry.key().clone(), entry.value().clone()))
+                    .collect();
 
-                for key in to_remove {
-                    buffers.remove(&key);
+                // Age is measured from completion so long-running executions
+                // are never collected while they are still tracing
+                for (key, buffer) in entries {
+                    // Replays carry the original completion time, so they
+                    // age from when they were imported instead
+                    let finished_at = match (&buffer.replay, *buffer.completion_time.lock().await) {
+                        (Some(replay), _) => replay.imported_at,
+                        (None, Some(completion_time)) => completion_time,
+                        (None, None) => continue,
+                    };
+                    if current_time.saturating_sub(finished_at) > max_age {
+                        Self::remove_execution(&buffers, &key).await;
+                    }
                 }
             }
         });
@@ -258,138 +1028,162 @@ impl BpftraceServer {
         &self,
         Parameters(ListProbesRequest { filter }): Parameters<ListProbesRequest>,
     ) -> Result<CallToolResult, McpError> {
-        let mut cmd = Command::new("sudo");
-        cmd.arg("-S").arg("bpftrace").arg("-l");
-        
-        if let Some(filter) = filter {
-            cmd.arg(filter);
-        }
+        let probes = self.list_probe_names(filter).await?;
 
-        cmd.stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
+        Ok(responses::success(&responses::ListProbesResponse {
+            count: probes.len(),
+            probes,
+        }))
+    }
 
-        let mut child = match cmd.spawn() {
-            Ok(child) => child,
-            Err(e) => {
-                return Err(McpError::internal_error(
-                    "Failed to spawn bpftrace process",
-                    Some(json!({"error": e.to_string()})),
-                ));
-            }
-        };
+    #[tool(description = "Get bpftrace system information and capabilities")]
+    async fn bpf_info(&self) -> Result<CallToolResult, McpError> {
+        let output = Self::run_discovery_command(
+            &self.sudo_password,
+            &["--info".to_string()],
+            self.config.discovery_timeout(),
+        )
+        .await?;
 
-        // Send password to sudo
-        if let Some(mut stdin) = child.stdin.take() {
-            use tokio::io::AsyncWriteExt;
-            let _ = stdin
-                .write_all(format!("{}\n", self.sudo_password).as_bytes())
-                .await;
-            let _ = stdin.flush().await;
-        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        
+        Ok(CallToolResult::success(vec![Content::text(stdout.to_string())]))
+    }
 
-        let output = match child.wait_with_output().await {
-            Ok(output) => output,
-            Err(e) => {
-                return Err(McpError::internal_error(
-                    "Failed to execute bpftrace",
-                    Some(json!({"error": e.to_string()})),
-                ));
-            }
+    #[tool(description = "Get curated bpftrace usage examples with explanations for a helper name or a task keyword, plus complete bundled programs that use it")]
+    async fn get_helper_examples(
+        &self,
+        Parameters(GetHelperExamplesRequest { query, limit }): Parameters<GetHelperExamplesRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let examples = helpers::find(&query, limit);
+        let related_programs = helpers::related_programs(&query, &examples);
+        let (available_helpers, available_tasks) = if examples.is_empty() && related_programs.is_empty() {
+            (helpers::helper_names(), helpers::task_keywords())
+        } else {
+            (Vec::new(), Vec::new())
         };
 
-        if !output.status.success() {
-            return Err(McpError::internal_error(
-                "Bpftrace command failed",
-                Some(json!({"stderr": String::from_utf8_lossy(&output.stderr).to_string()})),
-            ));
-        }
-
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let probes: Vec<String> = stdout
-            .lines()
-            .filter(|line| !line.is_empty() && !line.starts_with("[sudo]"))
-            .map(|s| s.to_string())
-            .collect();
-
-        Ok(CallToolResult::success(vec![Content::text(
-            json!({
-                "probes": probes,
-                "count": probes.len()
-            }).to_string()
-        )]))
+        Ok(responses::success(&responses::HelperExamplesResponse {
+            query,
+            examples,
+            related_programs,
+            available_helpers,
+            available_tasks,
+        }))
     }
 
-    #[tool(description = "Get bpftrace system information and capabilities")]
-    async fn bpf_info(&self) -> Result<CallToolResult, McpError> {
-        let mut cmd = Command::new("sudo");
-        cmd.arg("-S")
-            .arg("bpftrace")
-            .arg("--info")
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
+    #[tool(description = "Execute a bpftrace program with buffered output")]
+    async fn exec_program(
+        &self,
+        Parameters(ExecProgramRequest {
+            program,
+            timeout,
+            target_comm,
+            pids,
+            export_metrics,
+            long_running,
+        }): Parameters<ExecProgramRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        // Reject oversized programs before anything is spawned
+        limits::check_program(&program, &self.config).map_err(limits::LimitViolation::into_error)?;
 
-        let mut child = match cmd.spawn() {
-            Ok(child) => child,
-            Err(e) => {
-                return Err(McpError::internal_error(
-                    "Failed to spawn bpftrace process",
-                    Some(json!({"error": e.to_string()})),
+        // Validate timeout
+        let mut long_running_permit = None;
+        let timeout = if long_running {
+            if !self.config.allow_long_running {
+                return Err(McpError::invalid_params(
+                    "Long-running mode is disabled on this server",
+                    Some(json!({"hint": "The operator can enable it with BPFTRACE_MCP_ALLOW_LONG_RUNNING=1"})),
                 ));
             }
-        };
-
-        // Send password to sudo
-        if let Some(mut stdin) = child.stdin.take() {
-            use tokio::io::AsyncWriteExt;
-            let _ = stdin
-                .write_all(format!("{}\n", self.sudo_password).as_bytes())
-                .await;
-            let _ = stdin.flush().await;
-        }
-
-        let output = match child.wait_with_output().await {
-            Ok(output) => output,
-            Err(e) => {
-                return Err(McpError::internal_error(
-                    "Failed to execute bpftrace",
-                    Some(json!({"error": e.to_string()})),
-                ));
+            // Taken before anything awaits so concurrent calls cannot both
+            // pass the check; held until the execution finishes
+            match self.long_running_slots.clone().try_acquire_owned() {
+                Ok(permit) => long_running_permit = Some(permit),
+                Err(_) => {
+                    return Err(McpError::invalid_params(
+                        "Too many long-running executions are active",
+                        Some(json!({"active": self.config.max_long_running, "max_long_running": self.config.max_long_running})),
+                    ));
+                }
             }
+            timeout.clamp(1, self.config.long_running_max_timeout)
+        } else {
+            timeout.clamp(1, self.config.max_timeout)
         };
 
-        if !output.status.success() {
-            return Err(McpError::internal_error(
-                "Bpftrace command failed",
-                Some(json!({"stderr": String::from_utf8_lossy(&output.stderr).to_string()})),
-            ));
+        // Resolve target processes
+        let target_pids = self.resolve_targets(pids, target_comm.as_deref())?;
+
+        // Restrict the program to the targets, either with a single pid-set
+        // predicate or with one attached instance per process
+        let per_pid_attach = !target_pids.is_empty()
+            && program::parse_clauses(&program)
+                .iter()
+                .any(|clause| clause.needs_process_context());
+        if per_pid_attach {
+            limits::check_attach_pids(target_pids.len(), &self.config)
+                .map_err(limits::LimitViolation::into_error)?;
         }
 
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        
-        Ok(CallToolResult::success(vec![Content::text(stdout.to_string())]))
-    }
+        // Expand wildcard probes up front so overly broad ones are rejected
+        // and the attach report can point out the ones that match nothing.
+        // In per-PID mode every instance attaches the whole expansion.
+        let mut attach_report = attach::AttachReport::for_program(&program);
+        self.expand_wildcards(&mut attach_report, &target_pids).await?;
+        let instances = if per_pid_attach { target_pids.len() } else { 1 };
+        limits::check_wildcard_breadth(attach_report.wildcard_matches(), instances, &self.config)
+            .map_err(limits::LimitViolation::into_error)?;
 
-    #[tool(description = "Execute a bpftrace program with buffered output")]
-    async fn exec_program(
-        &self,
-        Parameters(ExecProgramRequest { program, timeout }): Parameters<ExecProgramRequest>,
-    ) -> Result<CallToolResult, McpError> {
-        // Validate timeout
-        let timeout = timeout.clamp(1, 60);
+        let mut program = program;
+        let mut attach_pids = Vec::new();
+        if per_pid_attach {
+            attach_pids = target_pids.clone();
+        } else if !target_pids.is_empty() {
+            program = program::add_predicate(&program, &targeting::pid_predicate(&target_pids));
+        }
 
         // Generate execution ID
-        let execution_id = format!("exec_{}", Uuid::new_v4().to_string()[..8].to_string());
+        let execution_id = format!("exec_{}", &Uuid::new_v4().to_string()[..8]);
 
         // Create buffer
-        let buffer = ExecutionBuffer::new(10000);
+        let mut buffer = ExecutionBuffer::new(10000, Duration::from_secs(timeout));
+        buffer.export_metrics = export_metrics;
+        buffer.long_running = long_running;
+        buffer.target_pids = target_pids.clone();
+        buffer.per_pid_attach = per_pid_attach;
+        buffer.attach_report = Arc::new(Mutex::new(attach_report));
+        buffer.program = program.clone();
+        if long_running {
+            match spill::SpillFile::create(&self.config.spill_dir, &execution_id, self.config.max_spill_bytes).await {
+                Ok(spill) => buffer.spill = Some(spill),
+                Err(e) => {
+                    return Err(McpError::internal_error(
+                        "Failed to create output spill file",
+                        Some(json!({"error": e.to_string()})),
+                    ));
+                }
+            }
+        }
         self.execution_buffers
             .insert(execution_id.clone(), buffer.clone());
 
+        if let Some(journal) = &self.journal {
+            journal
+                .record(journal::JournalEntry::Started {
+                    execution_id: execution_id.clone(),
+                    time: buffer.creation_time,
+                    timeout,
+                    program_hash: journal::program_hash(&program),
+                    spill_path: buffer.spill.as_ref().map(|spill| spill.path().to_path_buf()),
+                })
+                .await;
+        }
+
         // Start execution in background
         let password = self.sudo_password.to_string();
         let exec_id = execution_id.clone();
+        let journal = self.journal.clone();
         tokio::spawn(async move {
             BpftraceServer::run_bpftrace_program(
                 exec_id,
@@ -397,15 +1191,19 @@ impl BpftraceServer {
                 Duration::from_secs(timeout),
                 password,
                 buffer,
+                attach_pids,
+                journal,
             )
             .await;
+            drop(long_running_permit);
         });
 
         // Give it a moment to check for syntax errors
         sleep(Duration::from_millis(500)).await;
 
-        // Check if it failed immediately (syntax error)
-        if let Some(buffer) = self.execution_buffers.get(&execution_id) {
+        // Check if it failed immediately (syntax error). The buffer is cloned
+        // so the map shard is not locked across the awaits below
+        if let Some(buffer) = self.execution_buffers.get(&execution_id).map(|buffer| buffer.clone()) {
             let status = buffer.status.lock().await.clone();
             if status == "failed" {
                 let error_msg = buffer
@@ -421,13 +1219,96 @@ impl BpftraceServer {
             }
         }
 
-        Ok(CallToolResult::success(vec![Content::text(
-            json!({
-                "execution_id": execution_id,
-                "status": "started",
-                "message": format!("Program execution started with timeout of {}s", timeout)
-            }).to_string()
-        )]))
+        Ok(responses::success(&responses::ExecProgramResponse {
+            execution_id,
+            status: "started".to_string(),
+            message: format!("Program execution started with timeout of {}s", timeout),
+            target_comm,
+            target_pids,
+            per_pid_attach,
+            long_running,
+        }))
+    }
+
+    #[tool(description = "Collect a system call summary or CPU profile with perf (degraded mode for hosts without bpftrace); read the output with get_result")]
+    async fn perf_trace(
+        &self,
+        Parameters(PerfTraceRequest {
+            mode,
+            duration,
+            target_comm,
+            pids,
+            frequency,
+        }): Parameters<PerfTraceRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let duration = duration.clamp(1, self.config.max_timeout);
+        let frequency = frequency.clamp(1, 10000);
+        let target_pids = self.resolve_targets(pids, target_comm.as_deref())?;
+
+        let execution_id = format!("exec_{}", &Uuid::new_v4().to_string()[..8]);
+        if let Err(e) = config::ensure_private_dir(&self.config.spill_dir) {
+            return Err(McpError::internal_error(
+                "Failed to create perf data directory",
+                Some(json!({"error": e.to_string()})),
+            ));
+        }
+        let data_file = self.config.spill_dir.join(format!("{}.perf.data", execution_id));
+        let commands = perf::commands(mode, duration, &target_pids, frequency, &data_file);
+
+        let mut buffer = ExecutionBuffer::new(10000, Duration::from_secs(duration));
+        buffer.tracer = "perf";
+        buffer.target_pids = target_pids.clone();
+        buffer.program = commands
+            .iter()
+            .map(|args| format!("perf {}", args.join(" ")))
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.execution_buffers
+            .insert(execution_id.clone(), buffer.clone());
+
+        if let Some(journal) = &self.journal {
+            journal
+                .record(journal::JournalEntry::Started {
+                    execution_id: execution_id.clone(),
+                    time: buffer.creation_time,
+                    timeout: duration,
+                    program_hash: journal::program_hash(&buffer.program),
+                    spill_path: None,
+                })
+                .await;
+        }
+
+        // perf report can take a while on a large profile, so allow for it
+        // on top of the collection time
+        let deadline = Duration::from_secs(duration) + self.config.discovery_timeout();
+        let password = self.sudo_password.to_string();
+        let exec_id = execution_id.clone();
+        let journal = self.journal.clone();
+        tokio::spawn(async move {
+            perf::run(&exec_id, commands, deadline, password, buffer.clone(), journal.as_ref()).await;
+            let _ = tokio::fs::remove_file(&data_file).await;
+
+            if let Some(journal) = &journal {
+                let status = buffer.status.lock().await.clone();
+                journal
+                    .record(journal::JournalEntry::Finished {
+                        execution_id: exec_id,
+                        time: buffer.completion_time.lock().await.unwrap_or_default(),
+                        status,
+                    })
+                    .await;
+            }
+        });
+
+        Ok(responses::success(&responses::PerfTraceResponse {
+            execution_id,
+            status: "started".to_string(),
+            backend: "perf".to_string(),
+            mode,
+            message: format!("perf collection started for {}s", duration),
+            target_comm,
+            target_pids,
+        }))
     }
 
     #[tool(description = "Get buffered output from a bpftrace execution")]
@@ -439,14 +1320,27 @@ impl BpftraceServer {
             limit,
         }): Parameters<GetResultRequest>,
     ) -> Result<CallToolResult, McpError> {
-        if let Some(buffer) = self.execution_buffers.get(&execution_id) {
-            let lines = buffer.lines.lock().await;
-            let total_lines = lines.len();
-            let end_index = (offset + limit).min(total_lines);
-            let output_lines: Vec<String> = lines[offset..end_index].to_vec();
+        // Cloned so the map shard is not locked while the spill file is read
+        if let Some(buffer) = self.execution_buffers.get(&execution_id).map(|buffer| buffer.clone()) {
+            let (total_lines, output_lines) = buffer.read_lines(offset, limit).await.map_err(|e| {
+                McpError::internal_error(
+                    "Failed to read spilled output",
+                    Some(json!({"error": e.to_string()})),
+                )
+            })?;
+            let end_index = offset + output_lines.len();
 
             let status = buffer.status.lock().await.clone();
             let error_message = buffer.error_message.lock().await.clone();
+            let lost_events = *buffer.lost_events.lock().await;
+            let lost_events_warning = (lost_events > 0).then(|| {
+                format!(
+                    "{} events were lost because the perf ring buffer overflowed; the output is incomplete. \
+                     Raise the buffer size by adding `config = {{ perf_rb_pages = 1024 }}` at the top of the program \
+                     (or setting BPFTRACE_PERF_RB_PAGES), or aggregate in maps instead of printing every event.",
+                    lost_events
+                )
+            });
             
             let duration = if let Some(completion_time) = *buffer.completion_time.lock().await {
                 Some(completion_time - buffer.creation_time)
@@ -454,18 +1348,23 @@ impl BpftraceServer {
                 None
             };
 
-            Ok(CallToolResult::success(vec![Content::text(
-                json!({
-                    "execution_id": execution_id,
-                    "status": status,
-                    "lines_total": total_lines,
-                    "lines_returned": output_lines.len(),
-                    "output": output_lines,
-                    "has_more": end_index < total_lines,
-                    "error_message": error_message,
-                    "duration": duration
-                }).to_string()
-            )]))
+            let (progress_percent, time_remaining_secs) = buffer.progress().await;
+
+            Ok(responses::success(&responses::GetResultResponse {
+                execution_id,
+                status,
+                progress_percent,
+                time_remaining_secs,
+                lines_total: total_lines,
+                lines_returned: output_lines.len(),
+                output: output_lines,
+                has_more: end_index < total_lines,
+                error_message,
+                duration,
+                lost_events,
+                lost_events_warning,
+                spill_path: buffer.spill.as_ref().map(|spill| spill.path().display().to_string()),
+            }))
         } else {
             Err(McpError::invalid_params(
                 "Execution ID not found",
@@ -473,6 +1372,249 @@ impl BpftraceServer {
             ))
         }
     }
+
+    #[tool(description = "List known bpftrace executions with their status and progress")]
+    async fn list_executions(&self) -> Result<CallToolResult, McpError> {
+        let buffers: Vec<(String, ExecutionBuffer)> = self
+            .execution_buffers
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+
+        let mut executions = Vec::new();
+        for (execution_id, buffer) in buffers {
+            let status = buffer.status.lock().await.clone();
+            let lines_total = buffer.line_count().await;
+            let (progress_percent, time_remaining_secs) = buffer.progress().await;
+            executions.push(responses::ExecutionSummary {
+                execution_id,
+                status,
+                creation_time: buffer.creation_time,
+                lines_total,
+                progress_percent,
+                time_remaining_secs,
+            });
+        }
+        executions.sort_by_key(|e| e.creation_time);
+
+        Ok(responses::success(&responses::ListExecutionsResponse {
+            count: executions.len(),
+            executions,
+        }))
+    }
+
+    #[tool(description = "Summarize a bpftrace execution's output server-side: events per second over time, the most frequent keys and min/max/avg of a numeric field, instead of paging through raw lines")]
+    async fn summarize_execution(
+        &self,
+        Parameters(SummarizeExecutionRequest {
+            execution_id,
+            key_field,
+            top_n,
+            value_field,
+            bucket_secs,
+        }): Parameters<SummarizeExecutionRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let buffer = match self.execution_buffers.get(&execution_id) {
+            Some(buffer) => buffer.clone(),
+            None => {
+                return Err(McpError::invalid_params(
+                    "Execution ID not found",
+                    None,
+                ));
+            }
+        };
+
+        let read_error = |e: std::io::Error| {
+            McpError::internal_error(
+                "Failed to read spilled output",
+                Some(json!({"error": e.to_string()})),
+            )
+        };
+        // Streamed line by line: a spilled execution may hold hours of output
+        let mut lines = buffer.output_lines().await.map_err(read_error)?;
+        let mut lines_total = 0;
+        let mut events = 0;
+        let mut keys = summary::KeyCounter::default();
+        let mut values = value_field.as_deref().map(summary::ValueAccumulator::new);
+        while let Some(line) = lines.next_line().await.map_err(read_error)? {
+            lines_total += 1;
+            if !summary::is_event(&line) {
+                continue;
+            }
+            events += 1;
+            keys.add(&line, key_field.as_deref());
+            if let Some(values) = values.as_mut() {
+                values.add(&line);
+            }
+        }
+        let (bucket_secs, rate) = summary::rate(&buffer.event_rate.lock().await, bucket_secs);
+        let status = buffer.status.lock().await.clone();
+
+        Ok(responses::success(&responses::SummarizeExecutionResponse {
+            execution_id,
+            status,
+            lines_total,
+            events,
+            // Without a spill file only the first max_lines lines are kept
+            output_truncated: match &buffer.spill {
+                Some(spill) => spill.truncated().await,
+                None => lines_total > buffer.max_lines,
+            },
+            bucket_secs,
+            rate,
+            top_keys: keys.top(top_n),
+            key_field,
+            value_stats: values.and_then(summary::ValueAccumulator::finish),
+        }))
+    }
+
+    #[tool(description = "Export an execution (program, metadata, system snapshot, full output, parsed maps and histograms) to a JSON bundle file for sharing")]
+    async fn export_bundle(
+        &self,
+        Parameters(ExportBundleRequest {
+            execution_id,
+            file_name,
+            include_bpftrace_info,
+        }): Parameters<ExportBundleRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let buffer = match self.execution_buffers.get(&execution_id) {
+            Some(buffer) => buffer.clone(),
+            None => {
+                return Err(McpError::invalid_params(
+                    "Execution ID not found",
+                    None,
+                ));
+            }
+        };
+
+        let path = self.bundle_path(file_name.unwrap_or_else(|| format!("{}.bundle.json", execution_id)))?;
+
+        let bpftrace_info = if include_bpftrace_info && self.backend == perf::Backend::Bpftrace {
+            Self::run_discovery_command(
+                &self.sudo_password,
+                &["--info".to_string()],
+                self.config.discovery_timeout(),
+            )
+            .await
+            .ok()
+            .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+        } else {
+            None
+        };
+
+        let io_error = |e: std::io::Error| {
+            McpError::internal_error("Failed to write bundle", Some(json!({"error": e.to_string()})))
+        };
+        let bundle = bundle::Bundle::from_execution(&execution_id, &buffer, bundle::system_info(bpftrace_info))
+            .await
+            .map_err(io_error)?;
+        let lines = bundle.output.line_count();
+        let maps = bundle.maps.len();
+        let histograms = bundle.histograms.len();
+        let bytes = bundle.write(&path, self.config.max_bundle_bytes).await.map_err(io_error)?;
+
+        Ok(responses::success(&responses::ExportBundleResponse {
+            execution_id,
+            path: path.display().to_string(),
+            bytes,
+            lines,
+            maps,
+            histograms,
+        }))
+    }
+
+    #[tool(description = "Import a bundle written by export_bundle as a read-only replay execution that get_result, summarize_execution and get_execution_info can inspect")]
+    async fn import_bundle(
+        &self,
+        Parameters(ImportBundleRequest { file_name }): Parameters<ImportBundleRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let path = self.bundle_path(file_name)?;
+        let bundle = bundle::Bundle::read(&path, self.config.max_bundle_bytes).await.map_err(|e| {
+            McpError::invalid_params(
+                "Failed to read bundle",
+                Some(json!({"path": path.display().to_string(), "error": e.to_string()})),
+            )
+        })?;
+
+        let exported_at = bundle.exported_at;
+        let system_info = bundle.system_info.clone();
+        let original_execution_id = bundle.execution.execution_id.clone();
+        let program = bundle.execution.program.clone();
+        let lines = bundle.output.len();
+
+        // Importing the same export again hands back the replay made from it
+        let existing = self.execution_buffers.iter().find_map(|entry| {
+            entry
+                .value()
+                .replay
+                .as_ref()
+                .filter(|replay| replay.is_import_of(&path, exported_at, &original_execution_id))
+                .map(|_| (entry.key().clone(), entry.value().clone()))
+        });
+        let (execution_id, buffer, already_imported) = match existing {
+            Some((execution_id, buffer)) => (execution_id, buffer, true),
+            None => {
+                let execution_id = format!("replay_{}", &Uuid::new_v4().to_string()[..8]);
+                let buffer = bundle.into_buffer(path);
+                self.execution_buffers.insert(execution_id.clone(), buffer.clone());
+                (execution_id, buffer, false)
+            }
+        };
+        let status = buffer.status.lock().await.clone();
+
+        Ok(responses::success(&responses::ImportBundleResponse {
+            execution_id,
+            original_execution_id,
+            already_imported,
+            status,
+            program,
+            lines,
+            exported_at,
+            system_info,
+        }))
+    }
+
+    #[tool(description = "Get metadata for a bpftrace execution, including which probes were requested and actually attached")]
+    async fn get_execution_info(
+        &self,
+        Parameters(GetExecutionInfoRequest { execution_id }): Parameters<GetExecutionInfoRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let buffer = match self.execution_buffers.get(&execution_id) {
+            Some(buffer) => buffer.clone(),
+            None => {
+                return Err(McpError::invalid_params(
+                    "Execution ID not found",
+                    None,
+                ));
+            }
+        };
+
+        let status = buffer.status.lock().await.clone();
+        let error_message = buffer.error_message.lock().await.clone();
+        let completion_time = *buffer.completion_time.lock().await;
+        let lost_events = *buffer.lost_events.lock().await;
+        let attach_report = buffer.attach_report.lock().await.snapshot();
+        let (progress_percent, time_remaining_secs) = buffer.progress().await;
+
+        Ok(responses::success(&responses::ExecutionInfoResponse {
+            execution_id,
+            status,
+            program: buffer.program,
+            timeout: buffer.timeout.as_secs(),
+            creation_time: buffer.creation_time,
+            completion_time,
+            progress_percent,
+            time_remaining_secs,
+            error_message,
+            target_pids: buffer.target_pids,
+            per_pid_attach: buffer.per_pid_attach,
+            long_running: buffer.long_running,
+            export_metrics: buffer.export_metrics,
+            lost_events,
+            attach_report,
+            replay_of: buffer.replay.as_deref().cloned(),
+        }))
+    }
 }
 
 #[tool_handler]
@@ -480,11 +1622,177 @@ impl ServerHandler for BpftraceServer {
     fn get_info(&self) -> ServerInfo {
         ServerInfo {
             protocol_version: ProtocolVersion::V_2024_11_05,
-            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            capabilities: ServerCapabilities::builder()
+                .enable_tools()
+                .enable_resources()
+                .enable_resources_subscribe()
+                .build(),
             server_info: Implementation::from_build_env(),
-            instructions: Some("MCP server for bpftrace - provides Linux kernel tracing capabilities".to_string()),
+            instructions: Some(match self.backend {
+                perf::Backend::Bpftrace => {
+                    "MCP server for bpftrace - provides Linux kernel tracing capabilities".to_string()
+                }
+                perf::Backend::Perf => "MCP server for bpftrace, running in degraded mode: bpftrace is not usable on this host, \
+                    so only system call summaries and CPU sampling are available through perf_trace"
+                    .to_string(),
+            }),
         }
     }
+
+    async fn list_resources(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListResourcesResult, McpError> {
+        let mut resources: Vec<(u64, Resource)> = self
+            .execution_buffers
+            .iter()
+            .map(|entry| {
+                let mut resource =
+                    RawResource::new(resources::output_uri(entry.key()), format!("{} output", entry.key()));
+                resource.description = Some("Output lines of a bpftrace execution".to_string());
+                resource.mime_type = Some("text/plain".to_string());
+                (entry.value().creation_time, resource.no_annotation())
+            })
+            .collect();
+        resources.sort_by_key(|(creation_time, _)| *creation_time);
+
+        // Output schemas of the tools offered by the active backend
+        let schemas = responses::output_schemas()
+            .into_iter()
+            .filter(|(tool, _)| self.tool_router.has_route(tool))
+            .map(|(tool, _)| {
+                let mut resource = RawResource::new(responses::schema_uri(tool), format!("{} output schema", tool));
+                resource.description = Some(format!("JSON Schema of the result returned by {}", tool));
+                resource.mime_type = Some("application/schema+json".to_string());
+                resource.no_annotation()
+            });
+
+        Ok(ListResourcesResult::with_all_items(
+            schemas
+                .chain(resources.into_iter().map(|(_, resource)| resource))
+                .collect(),
+        ))
+    }
+
+    async fn list_resource_templates(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListResourceTemplatesResult, McpError> {
+        let template = RawResourceTemplate {
+            uri_template: resources::OUTPUT_URI_TEMPLATE.to_string(),
+            name: "Execution output".to_string(),
+            description: Some(
+                "Output of a bpftrace execution; use offset/limit to read only lines added since the last read"
+                    .to_string(),
+            ),
+            mime_type: Some("text/plain".to_string()),
+        };
+        Ok(ListResourceTemplatesResult::with_all_items(vec![template.no_annotation()]))
+    }
+
+    async fn read_resource(
+        &self,
+        ReadResourceRequestParam { uri }: ReadResourceRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ReadResourceResult, McpError> {
+        if let Some(tool) = uri.strip_prefix(responses::SCHEMA_URI_PREFIX) {
+            let schema = responses::output_schema(tool)
+                .ok_or_else(|| McpError::resource_not_found("Unknown tool schema", Some(json!({"uri": uri}))))?;
+            return Ok(ReadResourceResult {
+                contents: vec![ResourceContents::TextResourceContents {
+                    mime_type: Some("application/schema+json".to_string()),
+                    text: serde_json::Value::Object(schema).to_string(),
+                    uri,
+                }],
+            });
+        }
+
+        let request = resources::parse_output_uri(&uri)
+            .ok_or_else(|| McpError::resource_not_found("Unknown resource URI", Some(json!({"uri": uri}))))?;
+        let buffer = self
+            .execution_buffers
+            .get(&request.execution_id)
+            .map(|buffer| buffer.clone())
+            .ok_or_else(|| McpError::resource_not_found("Execution ID not found", Some(json!({"uri": uri}))))?;
+
+        let (_, lines) = buffer
+            .read_lines(request.offset, request.limit.unwrap_or(usize::MAX))
+            .await
+            .map_err(|e| {
+                McpError::internal_error("Failed to read execution output", Some(json!({"error": e.to_string()})))
+            })?;
+
+        Ok(ReadResourceResult {
+            contents: vec![ResourceContents::TextResourceContents {
+                uri,
+                mime_type: Some("text/plain".to_string()),
+                text: lines.join("\n"),
+            }],
+        })
+    }
+
+    async fn subscribe(
+        &self,
+        SubscribeRequestParam { uri }: SubscribeRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        let request = resources::parse_output_uri(&uri)
+            .ok_or_else(|| McpError::resource_not_found("Unknown resource URI", Some(json!({"uri": uri}))))?;
+        let buffer = self
+            .execution_buffers
+            .get(&request.execution_id)
+            .map(|buffer| buffer.clone())
+            .ok_or_else(|| McpError::resource_not_found("Execution ID not found", Some(json!({"uri": uri}))))?;
+
+        let uri = resources::output_uri(&request.execution_id);
+        let key = (self.session, uri.clone());
+        if self.subscriptions.insert(key.clone(), ()).is_some() {
+            return Ok(());
+        }
+
+        // Poll for new lines and notify until the execution ends or the
+        // client unsubscribes
+        let subscriptions = self.subscriptions.clone();
+        let peer = context.peer;
+        tokio::spawn(async move {
+            let mut notified_lines = 0;
+            loop {
+                sleep(Duration::from_millis(500)).await;
+                if !subscriptions.contains_key(&key) {
+                    break;
+                }
+                let finished = *buffer.status.lock().await != "running";
+                let lines = buffer.line_count().await;
+                if lines != notified_lines || finished {
+                    notified_lines = lines;
+                    let notification = ResourceUpdatedNotificationParam { uri: uri.clone() };
+                    if peer.notify_resource_updated(notification).await.is_err() {
+                        break;
+                    }
+                }
+                if finished {
+                    break;
+                }
+            }
+            subscriptions.remove(&key);
+        });
+
+        Ok(())
+    }
+
+    async fn unsubscribe(
+        &self,
+        UnsubscribeRequestParam { uri }: UnsubscribeRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        let uri = resources::parse_output_uri(&uri)
+            .map(|request| resources::output_uri(&request.execution_id))
+            .unwrap_or(uri);
+        self.subscriptions.remove(&(self.session, uri));
+        Ok(())
+    }
 }
 
 fn verify_password(password: &str) -> Result<()> {
@@ -517,14 +1825,45 @@ fn verify_password(password: &str) -> Result<()> {
 async fn main() -> Result<()> {
     // Load .env file
     dotenv::dotenv().ok();
-    
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::from_default_env()
-                .add_directive("bpftrace_mcp_server=info".parse()?)
-                .add_directive("rmcp=info".parse()?),
-        )
-        .init();
+    let config = ServerConfig::from_env();
+
+    // `bpftrace-mcp-server admin [--socket PATH] <command>` talks to a
+    // running server's admin socket instead of serving MCP
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("admin") {
+        let mut command = &args[1..];
+        let mut socket = config.admin_socket.clone();
+        if command.first().map(String::as_str) == Some("--socket") && command.len() > 1 {
+            socket = Some(command[1].clone().into());
+            command = &command[2..];
+        }
+        let Some(socket) = socket else {
+            anyhow::bail!("no admin socket given; pass --socket PATH or set BPFTRACE_MCP_ADMIN_SOCKET");
+        };
+        print!("{}", admin::run_client(&socket, command)?);
+        return Ok(());
+    }
+
+    let env_filter = tracing_subscriber::EnvFilter::from_default_env()
+        .add_directive("bpftrace_mcp_server=info".parse()?)
+        .add_directive("rmcp=info".parse()?);
+    let log_file = match &config.log_file {
+        Some(path) => Some(logfile::LogFile::open(path.clone())?),
+        None => None,
+    };
+    match &log_file {
+        Some(log_file) => {
+            let log_file = log_file.clone();
+            tracing_subscriber::fmt()
+                .with_env_filter(env_filter)
+                .with_ansi(false)
+                .with_writer(move || logfile::LogWriter(log_file.clone()))
+                .init();
+        }
+        None => {
+            tracing_subscriber::fmt().with_env_filter(env_filter).init();
+        }
+    }
 
     // Get password from environment variable
     let sudo_password = match std::env::var("BPFTRACE_PASSWD") {
@@ -538,7 +1877,35 @@ async fn main() -> Result<()> {
         }
     };
     
-    let server = BpftraceServer::new(sudo_password);
+    // Recover executions that exited server processes left in flight
+    // before this process's own journal is created
+    config.prepare_dirs()?;
+    let mut in_flight = Vec::new();
+    let mut execution_journal = None;
+    if let Some(dir) = &config.journal_dir {
+        in_flight = journal::take_in_flight(dir)?;
+        let opened = journal::Journal::open(dir).await?;
+        info!("Journaling executions to {}", opened.path().display());
+        execution_journal = Some(opened);
+    }
+
+    let server = BpftraceServer::new(sudo_password, config, execution_journal);
+    server.recover_interrupted(in_flight).await;
+    server.start_probe_cache();
+
+    if let Some(socket) = &server.config.admin_socket {
+        admin::spawn(socket, server.clone(), log_file)?;
+    }
+
+    // Additional MCP transport sharing this server's executions with stdio
+    if let Some(socket) = &server.config.mcp_socket {
+        transport::spawn(socket, server.clone())?;
+    }
+
+    // Optional Prometheus endpoint for executions started with export_metrics
+    if let Some(addr) = &server.config.metrics_addr {
+        metrics::spawn(addr, server.execution_buffers.clone())?;
+    }
     
     info!("Starting bpftrace MCP server on stdio");
     
@@ -549,4 +1916,44 @@ async fn main() -> Result<()> {
     service.waiting().await?;
     
     Ok(())
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn map_values(buffer: &ExecutionBuffer) -> Vec<(String, f64)> {
+        buffer
+            .map_values
+            .lock()
+            .await
+            .iter()
+            .map(|((map, key, _), value)| (format!("{}[{}]", map, key), *value))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn new_map_print_replaces_previous_entries() {
+        let buffer = ExecutionBuffer::new(100, Duration::from_secs(10));
+        for line in ["@bytes[nginx]: 10", "@bytes[sshd]: 5", "", "@bytes[nginx]: 12", "@n: 1"] {
+            buffer.record_map_samples(line, None).await;
+        }
+        assert_eq!(
+            map_values(&buffer).await,
+            vec![("@bytes[nginx]".to_string(), 12.0), ("@n[]".to_string(), 1.0)]
+        );
+
+        // Each JSON line is a print of its own
+        buffer.record_map_samples(r#"{"type": "map", "data": {"@n": {"a": 1}}}"#, None).await;
+        buffer.record_map_samples(r#"{"type": "map", "data": {"@n": {"b": 2}}}"#, None).await;
+        assert_eq!(map_values(&buffer).await[1..], [("@n[b]".to_string(), 2.0)]);
+    }
+
+    #[tokio::test]
+    async fn map_prints_are_tracked_per_instance() {
+        let buffer = ExecutionBuffer::new(100, Duration::from_secs(10));
+        buffer.record_map_samples("@c[read]: 1", Some(1)).await;
+        buffer.record_map_samples("@c[read]: 2", Some(2)).await;
+        buffer.record_map_samples("@c[write]: 3", Some(1)).await;
+        assert_eq!(map_values(&buffer).await.len(), 3);
+    }
+}
@@ -0,0 +1,90 @@
+// Prometheus exposition of map values collected from executions started
+// with `export_metrics`. The endpoint is a deliberately tiny HTTP/1.0
+// responder on a blocking thread so it stays out of the MCP runtime.
+
+use crate::ExecutionBuffer;
+use dashmap::DashMap;
+use std::{
+    fmt::Write as _,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::Arc,
+    time::Duration,
+};
+use tracing::{info, warn};
+
+// Scrapes are served one at a time, so a client that stalls must not hold
+// the endpoint for longer than this
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+pub fn render(buffers: &DashMap<String, ExecutionBuffer>) -> String {
+    let mut body = String::new();
+    body.push_str("# HELP bpftrace_map_value Latest value of a bpftrace map entry printed by an execution\n");
+    body.push_str("# TYPE bpftrace_map_value gauge\n");
+
+    for entry in buffers.iter() {
+        let buffer = entry.value();
+        if !buffer.export_metrics {
+            continue;
+        }
+        for ((map, key, pid), value) in buffer.map_values.blocking_lock().iter() {
+            let _ = write!(
+                body,
+                "bpftrace_map_value{{execution_id=\"{}\",map=\"{}\",key=\"{}\"",
+                escape_label(entry.key()),
+                escape_label(map),
+                escape_label(key)
+            );
+            if let Some(pid) = pid {
+                let _ = write!(body, ",pid=\"{}\"", pid);
+            }
+            let _ = writeln!(body, "}} {}", value);
+        }
+    }
+
+    body
+}
+
+fn handle(mut stream: TcpStream, buffers: &DashMap<String, ExecutionBuffer>) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+
+    let (status, body) = if path == "/metrics" || path.starts_with("/metrics?") {
+        ("200 OK", render(buffers))
+    } else {
+        ("404 Not Found", "not found\n".to_string())
+    };
+    write!(
+        stream,
+        "HTTP/1.0 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+pub fn spawn(addr: &str, buffers: Arc<DashMap<String, ExecutionBuffer>>) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    info!("Serving Prometheus metrics on {}/metrics", addr);
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let result = stream.and_then(|stream| handle(stream, &buffers));
+            if let Err(e) = result {
+                warn!("metrics request failed: {}", e);
+            }
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,308 @@
+// Helpers for interpreting lines emitted by bpftrace.
+
+use rmcp::schemars::{self, JsonSchema};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// Matches "Lost N events" in text mode and
+// {"type": "lost_events", "data": {"events": N}} in JSON mode.
+pub fn parse_lost_events(line: &str) -> Option<u64> {
+    let trimmed = line.trim();
+    if trimmed.starts_with('{') {
+        let value: Value = serde_json::from_str(trimmed).ok()?;
+        if value.get("type")?.as_str()? != "lost_events" {
+            return None;
+        }
+        return value.get("data")?.get("events")?.as_u64();
+    }
+
+    // Anchored so a traced program printing "... lost 3 events" is not
+    // mistaken for bpftrace's own report
+    let rest = trimmed.strip_prefix("Lost ")?;
+    let (count, rest) = rest.split_once(' ')?;
+    if !rest.starts_with("event") {
+        return None;
+    }
+    count.parse().ok()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapSample {
+    pub map: String,
+    pub key: String,
+    pub value: f64,
+}
+
+// Extract scalar map entries from a printed map, e.g. `@bytes[nginx]: 4096`
+// in text mode or {"type": "map", "data": {"@bytes": {"nginx": 4096}}} in
+// JSON mode. Histograms and non-numeric values are ignored.
+pub fn parse_map_samples(line: &str) -> Vec<MapSample> {
+    let trimmed = line.trim();
+    if trimmed.starts_with('{') {
+        return parse_json_map_samples(trimmed);
+    }
+    if !trimmed.starts_with('@') {
+        return Vec::new();
+    }
+
+    let (map, key, rest) = match trimmed.find(['[', ':']) {
+        Some(i) if trimmed.as_bytes()[i] == b'[' => {
+            let close = match trimmed.rfind("]:") {
+                Some(close) if close > i => close,
+                _ => return Vec::new(),
+            };
+            (&trimmed[..i], &trimmed[i + 1..close], &trimmed[close + 2..])
+        }
+        Some(i) => (&trimmed[..i], "", &trimmed[i + 1..]),
+        None => return Vec::new(),
+    };
+
+    match rest.trim().parse::<f64>() {
+        Ok(value) => vec![MapSample {
+            map: map.to_string(),
+            key: key.to_string(),
+            value,
+        }],
+        Err(_) => Vec::new(),
+    }
+}
+
+fn parse_json_map_samples(line: &str) -> Vec<MapSample> {
+    let value: Value = match serde_json::from_str(line) {
+        Ok(value) => value,
+        Err(_) => return Vec::new(),
+    };
+    if value.get("type").and_then(Value::as_str) != Some("map") {
+        return Vec::new();
+    }
+    let Some(data) = value.get("data").and_then(Value::as_object) else {
+        return Vec::new();
+    };
+
+    let mut samples = Vec::new();
+    for (map, entries) in data {
+        match entries {
+            Value::Number(n) => samples.extend(n.as_f64().map(|value| MapSample {
+                map: map.clone(),
+                key: String::new(),
+                value,
+            })),
+            Value::Object(entries) => {
+                for (key, n) in entries {
+                    if let Some(value) = n.as_f64() {
+                        samples.push(MapSample {
+                            map: map.clone(),
+                            key: key.clone(),
+                            value,
+                        });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    samples
+}
+
+// Matches "Attaching N probes..." in text mode and
+// {"type": "attached_probes", "data": {"probes": N}} in JSON mode.
+pub fn parse_attached_probes(line: &str) -> Option<u64> {
+    let trimmed = line.trim();
+    if trimmed.starts_with('{') {
+        let value: Value = serde_json::from_str(trimmed).ok()?;
+        if value.get("type")?.as_str()? != "attached_probes" {
+            return None;
+        }
+        return value.get("data")?.get("probes")?.as_u64();
+    }
+
+    let rest = trimmed
+        .strip_prefix("Attaching ")
+        .or_else(|| trimmed.strip_prefix("Attached "))?;
+    let (count, rest) = rest.split_once(' ')?;
+    if !rest.starts_with("probe") {
+        return None;
+    }
+    count.parse().ok()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct HistogramBucket {
+    // Bucket label as printed, e.g. "[4K, 8K)" or "[0]"
+    pub range: String,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Histogram {
+    pub map: String,
+    pub key: String,
+    pub buckets: Vec<HistogramBucket>,
+}
+
+// "[4K, 8K)     5 |@@@@@     |" -> ("[4K, 8K)", 5)
+fn parse_histogram_bucket(line: &str) -> Option<HistogramBucket> {
+    if !line.starts_with('[') {
+        return None;
+    }
+    let close = line.find([']', ')'])?;
+    let (count, _) = line[close + 1..].split_once('|')?;
+    Some(HistogramBucket {
+        range: line[..=close].to_string(),
+        count: count.trim().parse().ok()?,
+    })
+}
+
+// Collects hist()/lhist() output printed in text mode one line at a time:
+// a "@map[key]:" header followed by bucket lines. Callers remove any
+// per-line prefix (such as the per-PID tag) before adding a line.
+#[derive(Debug, Default)]
+pub struct HistogramParser {
+    histograms: Vec<Histogram>,
+    current: Option<Histogram>,
+}
+
+impl HistogramParser {
+    pub fn add(&mut self, line: &str) {
+        let line = line.trim();
+        if let Some(bucket) = self.current.as_ref().and_then(|_| parse_histogram_bucket(line)) {
+            if let Some(histogram) = self.current.as_mut() {
+                histogram.buckets.push(bucket);
+            }
+            return;
+        }
+        self.histograms.extend(self.current.take().filter(|h| !h.buckets.is_empty()));
+
+        if let Some(header) = line.strip_prefix('@').and_then(|rest| rest.strip_suffix(':')) {
+            let (map, key) = match header.split_once('[') {
+                Some((map, key)) => (map, key.strip_suffix(']').unwrap_or(key)),
+                None => (header, ""),
+            };
+            self.current = Some(Histogram {
+                map: format!("@{}", map),
+                key: key.to_string(),
+                buckets: Vec::new(),
+            });
+        }
+    }
+
+    pub fn finish(mut self) -> Vec<Histogram> {
+        self.histograms.extend(self.current.filter(|h| !h.buckets.is_empty()));
+        self.histograms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_lost_events() {
+        assert_eq!(parse_lost_events("Lost 123 events"), Some(123));
+        assert_eq!(parse_lost_events("  Lost 1 event"), Some(1));
+        assert_eq!(
+            parse_lost_events(r#"{"type": "lost_events", "data": {"events": 7}}"#),
+            Some(7)
+        );
+    }
+
+    #[test]
+    fn ignores_lost_in_program_output() {
+        assert_eq!(parse_lost_events("conn lost 3 events"), None);
+        assert_eq!(parse_lost_events("lost 3 events"), None);
+        assert_eq!(parse_lost_events("Lost track of 3 events"), None);
+        assert_eq!(parse_lost_events(r#"{"type": "map", "data": {"events": 7}}"#), None);
+    }
+
+    #[test]
+    fn parses_text_map_samples() {
+        assert_eq!(
+            parse_map_samples("@bytes[nginx]: 4096"),
+            vec![MapSample {
+                map: "@bytes".to_string(),
+                key: "nginx".to_string(),
+                value: 4096.0,
+            }]
+        );
+        assert_eq!(
+            parse_map_samples("@total: 12"),
+            vec![MapSample {
+                map: "@total".to_string(),
+                key: String::new(),
+                value: 12.0,
+            }]
+        );
+        // Keys may themselves contain brackets and colons
+        assert_eq!(parse_map_samples("@[a[1]:b, 2]: 3")[0].key, "a[1]:b, 2");
+    }
+
+    #[test]
+    fn ignores_non_scalar_map_lines() {
+        assert!(parse_map_samples("@lat:").is_empty());
+        assert!(parse_map_samples("[4K, 8K)    5 |@@@@@   |").is_empty());
+        assert!(parse_map_samples("@comm[1]: nginx").is_empty());
+        assert!(parse_map_samples("read 4096 bytes").is_empty());
+    }
+
+    #[test]
+    fn parses_json_map_samples() {
+        let mut samples = parse_map_samples(r#"{"type": "map", "data": {"@bytes": {"nginx": 4096, "sshd": 12}, "@n": 3}}"#);
+        samples.sort_by(|a, b| a.key.cmp(&b.key));
+        assert_eq!(samples.len(), 3);
+        assert_eq!((samples[0].map.as_str(), samples[0].value), ("@n", 3.0));
+        assert_eq!((samples[1].key.as_str(), samples[1].value), ("nginx", 4096.0));
+        assert!(parse_map_samples(r#"{"type": "printf", "data": {"@n": 3}}"#).is_empty());
+    }
+
+    #[test]
+    fn parses_attached_probes() {
+        assert_eq!(parse_attached_probes("Attaching 3 probes..."), Some(3));
+        assert_eq!(parse_attached_probes("Attaching 1 probe..."), Some(1));
+        assert_eq!(parse_attached_probes("Attached 12 probes"), Some(12));
+        assert_eq!(
+            parse_attached_probes(r#"{"type": "attached_probes", "data": {"probes": 4}}"#),
+            Some(4)
+        );
+        assert_eq!(parse_attached_probes("Attaching to nginx"), None);
+        assert_eq!(parse_attached_probes("retrying: Attaching 3 probes"), None);
+    }
+
+    fn parse_histograms(lines: &[&str]) -> Vec<Histogram> {
+        let mut parser = HistogramParser::default();
+        for line in lines {
+            parser.add(line);
+        }
+        parser.finish()
+    }
+
+    #[test]
+    fn parses_histograms() {
+        let histograms = parse_histograms(&[
+            "Attaching 1 probe...",
+            "@lat[nginx]:",
+            "[4K, 8K)       5 |@@@@@                |",
+            "[8K, 16K)     20 |@@@@@@@@@@@@@@@@@@@@@|",
+            "",
+            "@sizes:",
+            "[0]            1 |@                    |",
+        ]);
+        assert_eq!(histograms.len(), 2);
+        assert_eq!((histograms[0].map.as_str(), histograms[0].key.as_str()), ("@lat", "nginx"));
+        assert_eq!(
+            histograms[0].buckets,
+            vec![
+                HistogramBucket { range: "[4K, 8K)".to_string(), count: 5 },
+                HistogramBucket { range: "[8K, 16K)".to_string(), count: 20 },
+            ]
+        );
+        assert_eq!((histograms[1].map.as_str(), histograms[1].key.as_str()), ("@sizes", ""));
+        assert_eq!(histograms[1].buckets[0].count, 1);
+    }
+
+    #[test]
+    fn skips_headers_without_buckets() {
+        // Scalar map headers and bucket-looking lines outside a histogram
+        assert!(parse_histograms(&["@lat:", "@n: 3", "[4K, 8K) 5 |@@|"]).is_empty());
+        assert!(parse_histograms(&["[4K, 8K)       5 |@@@@@     |"]).is_empty());
+    }
+}
@@ -0,0 +1,279 @@
+// Degraded-mode backend built on `perf`, for hosts where bpftrace is not
+// installed or the kernel lacks BPF support. It only covers the basics:
+// a system call summary (`perf trace -s`) and CPU sampling
+// (`perf record` + `perf report`).
+
+use crate::{journal, targeting, BpftraceServer, ExecutionBuffer};
+use rmcp::{handler::server::router::tool::ToolRouter, schemars};
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    process::Command,
+    time::{sleep, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Bpftrace,
+    Perf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PerfMode {
+    // Per-syscall counts and latencies from `perf trace -s`
+    Syscalls,
+    // Sampled stacks from `perf record -g`, summarised by `perf report`
+    CpuSample,
+}
+
+fn find_in_path(binary: &str) -> Option<PathBuf> {
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths)
+        .chain(["/usr/sbin", "/sbin"].iter().map(PathBuf::from))
+        .map(|dir| dir.join(binary))
+        .find(|candidate| candidate.is_file())
+}
+
+// The bpf() syscall exposes this sysctl whenever it is compiled in
+fn kernel_has_bpf() -> bool {
+    Path::new("/proc/sys/kernel/unprivileged_bpf_disabled").exists()
+}
+
+// `preference` is "bpftrace", "perf" or anything else for auto-detection
+pub fn detect_backend(preference: &str) -> Backend {
+    match preference {
+        "bpftrace" => Backend::Bpftrace,
+        "perf" => Backend::Perf,
+        _ => {
+            let bpftrace_usable = find_in_path("bpftrace").is_some() && kernel_has_bpf();
+            if !bpftrace_usable && find_in_path("perf").is_some() {
+                Backend::Perf
+            } else {
+                Backend::Bpftrace
+            }
+        }
+    }
+}
+
+fn target_args(pids: &[u32]) -> Vec<String> {
+    if pids.is_empty() {
+        vec!["-a".to_string()]
+    } else {
+        let pids: Vec<String> = pids.iter().map(u32::to_string).collect();
+        vec!["-p".to_string(), pids.join(",")]
+    }
+}
+
+// The perf invocations for a run, executed one after another
+pub fn commands(mode: PerfMode, duration: u64, pids: &[u32], frequency: u32, data_file: &Path) -> Vec<Vec<String>> {
+    let sleep_args = ["--", "sleep"].iter().map(|s| s.to_string()).chain([duration.to_string()]);
+    match mode {
+        PerfMode::Syscalls => {
+            let mut trace = vec!["trace".to_string(), "-s".to_string()];
+            trace.extend(target_args(pids));
+            trace.extend(sleep_args);
+            vec![trace]
+        }
+        PerfMode::CpuSample => {
+            let data_file = data_file.display().to_string();
+            let mut record = vec![
+                "record".to_string(),
+                "-g".to_string(),
+                "-F".to_string(),
+                frequency.to_string(),
+                "-o".to_string(),
+                data_file.clone(),
+            ];
+            record.extend(target_args(pids));
+            record.extend(sleep_args);
+            let report = ["report", "--stdio", "--sort", "comm,dso,sym", "-i"]
+                .iter()
+                .map(|s| s.to_string())
+                .chain([data_file])
+                .collect();
+            vec![record, report]
+        }
+    }
+}
+
+// Run the perf commands in sequence, streaming both output streams into
+// the buffer (perf reports its results on stderr).
+pub async fn run(
+    execution_id: &str,
+    commands: Vec<Vec<String>>,
+    timeout: Duration,
+    sudo_password: String,
+    buffer: ExecutionBuffer,
+    journal: Option<&journal::Journal>,
+) {
+    let deadline = Instant::now() + timeout;
+
+    for args in commands {
+        let mut child = match Command::new("sudo")
+            .arg("-S")
+            .arg("perf")
+            .args(&args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) => {
+                buffer.mark_failed(format!("Failed to spawn perf: {}", e)).await;
+                return;
+            }
+        };
+        let child_pid = child.id();
+        if let Some(pid) = child_pid {
+            buffer.child_pids.lock().await.push(pid);
+            if let Some(journal) = journal {
+                journal
+                    .record(journal::JournalEntry::Spawned {
+                        execution_id: execution_id.to_string(),
+                        child_pid: pid,
+                        start_time: targeting::process_start_time(pid),
+                        tracer: buffer.tracer.to_string(),
+                    })
+                    .await;
+            }
+        }
+
+        // Send password to sudo
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = stdin.write_all(format!("{}\n", sudo_password).as_bytes()).await;
+            let _ = stdin.flush().await;
+        }
+
+        let mut stdout_reader = BufReader::new(child.stdout.take().unwrap()).lines();
+        let mut stderr_reader = BufReader::new(child.stderr.take().unwrap()).lines();
+        let mut stdout_open = true;
+        let mut stderr_open = true;
+
+        while stdout_open || stderr_open {
+            tokio::select! {
+                _ = sleep(Duration::from_millis(100)) => {
+                    if Instant::now() > deadline {
+                        BpftraceServer::terminate_child(&mut child).await;
+                        buffer.add_line("[Execution timed out]".to_string()).await;
+                        buffer.mark_failed("Timeout".to_string()).await;
+                        break;
+                    }
+                }
+                _ = buffer.cancel.cancelled() => {
+                    BpftraceServer::terminate_child(&mut child).await;
+                    buffer.add_line("[Execution cancelled]".to_string()).await;
+                    buffer.mark_failed("Cancelled".to_string()).await;
+                    break;
+                }
+                line = stdout_reader.next_line(), if stdout_open => match line {
+                    Ok(Some(line)) => buffer.add_line(line).await,
+                    _ => stdout_open = false,
+                },
+                line = stderr_reader.next_line(), if stderr_open => match line {
+                    Ok(Some(line)) if line.starts_with("[sudo] password") => {}
+                    Ok(Some(line)) => buffer.add_line(line).await,
+                    _ => stderr_open = false,
+                },
+            }
+        }
+
+        let status = child.wait().await;
+        if let Some(pid) = child_pid {
+            buffer.child_pids.lock().await.retain(|p| *p != pid);
+        }
+        if *buffer.status.lock().await != "running" {
+            return;
+        }
+        match status {
+            Ok(status) if status.success() => {}
+            Ok(status) => {
+                buffer.mark_failed(format!("perf {} exited with {}", args[0], status)).await;
+                return;
+            }
+            Err(e) => {
+                buffer.mark_failed(format!("Failed to wait for perf: {}", e)).await;
+                return;
+            }
+        }
+    }
+
+    buffer.mark_completed().await;
+}
+
+// Tools that need a working bpftrace
+const BPFTRACE_TOOLS: &[&str] = &["list_probes", "bpf_info", "exec_program", "get_helper_examples"];
+
+// Descriptions of the shared tools when only perf is available
+const DEGRADED_DESCRIPTIONS: &[(&str, &str)] = &[
+    ("get_result", "Get buffered output from a perf_trace execution"),
+    ("list_executions", "List known perf_trace executions with their status and progress"),
+    (
+        "get_execution_info",
+        "Get metadata for a perf_trace execution, including the perf command that was run",
+    ),
+    (
+        "summarize_execution",
+        "Summarize a perf_trace execution's output server-side: lines per second over time, the most frequent keys and min/max/avg of a numeric column",
+    ),
+];
+
+// Shape the tool list for the selected backend: perf_trace only exists in
+// degraded mode, where the bpftrace tools are dropped and the remaining
+// descriptions say what the server can still do.
+pub fn adapt_tools(router: &mut ToolRouter<BpftraceServer>, backend: Backend) {
+    if backend == Backend::Bpftrace {
+        router.map.remove("perf_trace");
+        return;
+    }
+
+    for tool in BPFTRACE_TOOLS {
+        router.map.remove(*tool);
+    }
+    for (tool, description) in DEGRADED_DESCRIPTIONS {
+        if let Some(route) = router.map.get_mut(*tool) {
+            route.attr.description = Some((*description).into());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(command: &[String]) -> Vec<&str> {
+        command.iter().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn syscall_summary_command() {
+        let system_wide = commands(PerfMode::Syscalls, 5, &[], 99, Path::new("/spill/x.perf.data"));
+        assert_eq!(system_wide.len(), 1);
+        assert_eq!(args(&system_wide[0]), ["trace", "-s", "-a", "--", "sleep", "5"]);
+
+        let targeted = commands(PerfMode::Syscalls, 5, &[12, 34], 99, Path::new("/spill/x.perf.data"));
+        assert_eq!(args(&targeted[0]), ["trace", "-s", "-p", "12,34", "--", "sleep", "5"]);
+    }
+
+    #[test]
+    fn cpu_sample_records_then_reports() {
+        let profile = commands(PerfMode::CpuSample, 10, &[7], 49, Path::new("/spill/x.perf.data"));
+        assert_eq!(profile.len(), 2);
+        assert_eq!(
+            args(&profile[0]),
+            ["record", "-g", "-F", "49", "-o", "/spill/x.perf.data", "-p", "7", "--", "sleep", "10"]
+        );
+        assert_eq!(
+            args(&profile[1]),
+            ["report", "--stdio", "--sort", "comm,dso,sym", "-i", "/spill/x.perf.data"]
+        );
+    }
+}
@@ -0,0 +1,198 @@
+// Catalog of the probes `bpftrace -l` reports, kept in memory and on disk
+// so list_probes and wildcard expansion do not pay for a full listing on
+// every call. Cache files are keyed by kernel release, and a catalog built
+// on a different kernel than the running one is never used.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    io,
+    path::PathBuf,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{fs, sync::RwLock};
+
+// Probe type aliases accepted by bpftrace, expanded before matching
+const ALIASES: &[(&str, &str)] = &[
+    ("t", "tracepoint"),
+    ("k", "kprobe"),
+    ("kr", "kretprobe"),
+    ("u", "uprobe"),
+    ("ur", "uretprobe"),
+    ("U", "usdt"),
+    ("s", "software"),
+    ("h", "hardware"),
+    ("f", "fentry"),
+    ("fr", "fexit"),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Catalog {
+    pub kernel: String,
+    pub fetched_at: u64,
+    pub probes: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProbeCache {
+    dir: PathBuf,
+    catalog: Arc<RwLock<Option<Catalog>>>,
+}
+
+// Release plus build string, so a rebuilt kernel with the same release
+// also invalidates the catalog
+pub fn kernel_version() -> String {
+    let read = |path: &str| std::fs::read_to_string(path).unwrap_or_default().trim().to_string();
+    format!("{} {}", read("/proc/sys/kernel/osrelease"), read("/proc/sys/kernel/version"))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+// `*` matches any run of characters, `?` any single one
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == b'?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == b'*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+fn expand_alias(filter: &str) -> String {
+    if let Some((kind, rest)) = filter.split_once(':') {
+        if let Some((_, full)) = ALIASES.iter().find(|(alias, _)| *alias == kind) {
+            return format!("{}:{}", full, rest);
+        }
+    }
+    filter.to_string()
+}
+
+impl ProbeCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            catalog: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    fn file_for(&self, kernel: &str) -> PathBuf {
+        let release = kernel.split_whitespace().next().unwrap_or("unknown");
+        let release: String = release
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+            .collect();
+        self.dir.join(format!("probes-{}.json", release))
+    }
+
+    // Load the on-disk catalog for the running kernel, if there is one
+    pub async fn load(&self) -> Option<u64> {
+        let kernel = kernel_version();
+        let contents = fs::read(self.file_for(&kernel)).await.ok()?;
+        let catalog: Catalog = serde_json::from_slice(&contents).ok()?;
+        if catalog.kernel != kernel {
+            return None;
+        }
+        let fetched_at = catalog.fetched_at;
+        *self.catalog.write().await = Some(catalog);
+        Some(fetched_at)
+    }
+
+    // Replace the catalog with a fresh listing and persist it
+    pub async fn store(&self, probes: Vec<String>) -> io::Result<PathBuf> {
+        let catalog = Catalog {
+            kernel: kernel_version(),
+            fetched_at: now(),
+            probes,
+        };
+        let path = self.file_for(&catalog.kernel);
+        crate::config::ensure_private_dir(&self.dir)?;
+        // Write aside and rename so a crash never leaves a torn cache file
+        let partial = path.with_extension("json.tmp");
+        fs::write(&partial, serde_json::to_vec(&catalog)?).await?;
+        fs::rename(&partial, &path).await?;
+        *self.catalog.write().await = Some(catalog);
+        Ok(path)
+    }
+
+    // Seconds since the catalog was fetched, None without a usable catalog
+    pub async fn age(&self) -> Option<u64> {
+        let catalog = self.catalog.read().await;
+        let catalog = catalog.as_ref().filter(|catalog| catalog.kernel == kernel_version())?;
+        Some(now().saturating_sub(catalog.fetched_at))
+    }
+
+    // Probes matching `filter` (all probes when None). None means the
+    // cache cannot answer and bpftrace has to be asked: no catalog for the
+    // running kernel yet, or nothing matched, which is also what a filter
+    // for probes outside the kernel listing (uprobes, usdt) looks like.
+    pub async fn lookup(&self, filter: Option<&str>) -> Option<Vec<String>> {
+        let catalog = self.catalog.read().await;
+        let catalog = catalog.as_ref().filter(|catalog| catalog.kernel == kernel_version())?;
+        let Some(filter) = filter else {
+            return Some(catalog.probes.clone());
+        };
+
+        let pattern = expand_alias(filter);
+        let matches: Vec<String> = catalog
+            .probes
+            .iter()
+            .filter(|probe| glob_match(pattern.as_bytes(), probe.as_bytes()))
+            .cloned()
+            .collect();
+        (!matches.is_empty()).then_some(matches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        glob_match(pattern.as_bytes(), text.as_bytes())
+    }
+
+    #[test]
+    fn matches_globs() {
+        assert!(matches("kprobe:vfs_*", "kprobe:vfs_read"));
+        assert!(matches("kprobe:vfs_rea?", "kprobe:vfs_read"));
+        assert!(matches("*:*open*", "tracepoint:syscalls:sys_enter_openat"));
+        assert!(matches("kprobe:vfs_read", "kprobe:vfs_read"));
+        assert!(matches("kprobe:*", "kprobe:"));
+        assert!(!matches("kprobe:vfs_rea?", "kprobe:vfs_readv"));
+        assert!(!matches("kprobe:vfs_read", "kprobe:vfs_readv"));
+        assert!(!matches("kprobe:*write", "kprobe:vfs_writev"));
+        assert!(!matches("tracepoint:*", "kprobe:vfs_read"));
+    }
+
+    #[test]
+    fn backtracks_over_repeated_segments() {
+        assert!(matches("*ab*ab", "xabyabab"));
+        assert!(matches("a*b*c", "aXbYbZc"));
+        assert!(!matches("a*b*c", "aXbYbZ"));
+    }
+
+    #[test]
+    fn expands_probe_type_aliases() {
+        assert_eq!(expand_alias("t:syscalls:*"), "tracepoint:syscalls:*");
+        assert_eq!(expand_alias("kr:vfs_read"), "kretprobe:vfs_read");
+        assert_eq!(expand_alias("kprobe:vfs_read"), "kprobe:vfs_read");
+        assert_eq!(expand_alias("vfs_read"), "vfs_read");
+    }
+}
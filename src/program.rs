@@ -0,0 +1,313 @@
+// Lightweight structural view of a bpftrace program.
+//
+// This is not a full bpftrace parser: it only understands enough of the
+// top-level layout (probe lists, predicates, action blocks) to inspect and
+// rewrite probe clauses before the program is handed to bpftrace.
+
+#[derive(Debug, Clone)]
+pub struct ProbeClause {
+    pub probes: Vec<String>,
+    // Byte offset just past the last non-whitespace character of the header
+    header_end: usize,
+    // Byte range of the predicate including both slashes
+    predicate_span: Option<(usize, usize)>,
+}
+
+impl ProbeClause {
+    // BEGIN/END, interval and self probes do not run in the context of a
+    // traced task, so task-based filters make no sense for them.
+    pub fn is_task_independent(&self) -> bool {
+        self.probes.iter().any(|probe| {
+            let kind = probe.split(':').next().unwrap_or_default();
+            matches!(
+                kind,
+                "BEGIN" | "END" | "begin" | "end" | "interval" | "i" | "self"
+            )
+        })
+    }
+
+    // USDT probes and uprobes on bare library names are resolved against a
+    // process's mappings, which needs a dedicated `bpftrace -p` instance.
+    pub fn needs_process_context(&self) -> bool {
+        self.probes.iter().any(|probe| needs_process_context(probe))
+    }
+}
+
+pub fn needs_process_context(probe: &str) -> bool {
+    let mut parts = probe.splitn(3, ':');
+    let kind = parts.next().unwrap_or_default();
+    let target = parts.next().unwrap_or_default();
+    match kind {
+        "usdt" | "U" => true,
+        "uprobe" | "uretprobe" | "u" | "ur" => !target.starts_with('/'),
+        _ => false,
+    }
+}
+
+const NON_PROBE_KEYWORDS: &[&str] = &["struct", "union", "enum", "config", "macro", "fn", "import"];
+
+// Copy of the source with comments, preprocessor lines and string contents
+// blanked out so structural characters inside them are ignored.
+fn mask(program: &str) -> Vec<u8> {
+    let bytes = program.as_bytes();
+    let mut masked = bytes.to_vec();
+    let mut at_line_start = true;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let c = bytes[i];
+        let start = i;
+        match c {
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i += 2;
+                while i < bytes.len() && !(bytes[i] == b'*' && bytes.get(i + 1) == Some(&b'/')) {
+                    i += 1;
+                }
+                i = (i + 2).min(bytes.len());
+            }
+            b'#' if at_line_start => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'"' => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' {
+                    if bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                let end = i.min(bytes.len());
+                for byte in masked.iter_mut().take(end).skip(start + 1) {
+                    *byte = b'_';
+                }
+                at_line_start = false;
+                i += 1;
+                continue;
+            }
+            _ => {
+                at_line_start = c == b'\n' || (at_line_start && c.is_ascii_whitespace());
+                i += 1;
+                continue;
+            }
+        }
+        for byte in masked.iter_mut().take(i).skip(start) {
+            if *byte != b'\n' {
+                *byte = b' ';
+            }
+        }
+    }
+
+    masked
+}
+
+fn parse_header(program: &str, masked: &[u8], start: usize, brace: usize) -> Option<ProbeClause> {
+    let mut begin = start;
+    while begin < brace && masked[begin].is_ascii_whitespace() {
+        begin += 1;
+    }
+    let mut end = brace;
+    while end > begin && masked[end - 1].is_ascii_whitespace() {
+        end -= 1;
+    }
+    if begin == end {
+        return None;
+    }
+
+    let first_word: String = masked[begin..end]
+        .iter()
+        .take_while(|b| b.is_ascii_alphanumeric() || **b == b'_')
+        .map(|b| *b as char)
+        .collect();
+    if NON_PROBE_KEYWORDS.contains(&first_word.as_str()) {
+        return None;
+    }
+
+    // Probe names never start with '/', so the predicate opens at the first
+    // slash that follows whitespace (paths in uprobe names follow a ':').
+    let predicate_start =
+        (begin + 1..end).find(|&i| masked[i] == b'/' && masked[i - 1].is_ascii_whitespace());
+    let predicate_span = match predicate_start {
+        Some(p) if end - 1 > p && masked[end - 1] == b'/' => Some((p, end)),
+        _ => None,
+    };
+
+    let probes_end = predicate_span.map(|(p, _)| p).unwrap_or(end);
+    let probes: Vec<String> = program[begin..probes_end]
+        .split(',')
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty())
+        .collect();
+    if probes.is_empty() {
+        return None;
+    }
+
+    Some(ProbeClause {
+        probes,
+        header_end: end,
+        predicate_span,
+    })
+}
+
+pub fn parse_clauses(program: &str) -> Vec<ProbeClause> {
+    let masked = mask(program);
+    let mut clauses = Vec::new();
+    let mut depth = 0usize;
+    let mut header_start = 0;
+
+    for (i, c) in masked.iter().enumerate() {
+        match c {
+            b'{' => {
+                if depth == 0 {
+                    if let Some(clause) = parse_header(program, &masked, header_start, i) {
+                        clauses.push(clause);
+                    }
+                }
+                depth += 1;
+            }
+            b'}' => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    header_start = i + 1;
+                }
+            }
+            b';' if depth == 0 => header_start = i + 1,
+            _ => {}
+        }
+    }
+
+    clauses
+}
+
+// AND `condition` into the predicate of every task-bound probe clause,
+// adding a predicate where the clause has none.
+pub fn add_predicate(program: &str, condition: &str) -> String {
+    let mut result = program.to_string();
+
+    for clause in parse_clauses(program).iter().rev() {
+        if clause.is_task_independent() {
+            continue;
+        }
+        match clause.predicate_span {
+            Some((start, end)) => {
+                let existing = program[start + 1..end - 1].trim();
+                result.replace_range(start..end, &format!("/({}) && ({})/", condition, existing));
+            }
+            None => {
+                result.insert_str(clause.header_end, &format!(" /{}/", condition));
+            }
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adds_predicate_to_bare_clause() {
+        assert_eq!(
+            add_predicate("kprobe:do_sys_open { @[comm] = count(); }", "pid == 42"),
+            "kprobe:do_sys_open /pid == 42/ { @[comm] = count(); }"
+        );
+    }
+
+    #[test]
+    fn combines_with_existing_predicate() {
+        assert_eq!(
+            add_predicate("tracepoint:syscalls:sys_enter_read /comm == \"nginx\"/ { @ = count(); }", "pid == 42"),
+            "tracepoint:syscalls:sys_enter_read /(pid == 42) && (comm == \"nginx\")/ { @ = count(); }"
+        );
+    }
+
+    #[test]
+    fn keeps_division_inside_predicate() {
+        let program = "kprobe:vfs_read / arg2 / 2 > 1024 / { @ = count(); }";
+        let clauses = parse_clauses(program);
+        assert_eq!(clauses.len(), 1);
+        assert_eq!(clauses[0].probes, vec!["kprobe:vfs_read"]);
+        assert_eq!(
+            add_predicate(program, "pid == 42"),
+            "kprobe:vfs_read /(pid == 42) && (arg2 / 2 > 1024)/ { @ = count(); }"
+        );
+    }
+
+    #[test]
+    fn ignores_slashes_in_strings_and_paths() {
+        let program = "uprobe:/bin/bash:readline /str(arg0) == \"/tmp/x\"/ { printf(\"a/b {\\n\"); }";
+        let clauses = parse_clauses(program);
+        assert_eq!(clauses.len(), 1);
+        assert_eq!(clauses[0].probes, vec!["uprobe:/bin/bash:readline"]);
+        assert_eq!(
+            add_predicate(program, "pid == 42"),
+            "uprobe:/bin/bash:readline /(pid == 42) && (str(arg0) == \"/tmp/x\")/ { printf(\"a/b {\\n\"); }"
+        );
+    }
+
+    #[test]
+    fn parses_multi_probe_header_across_lines() {
+        let program = "kprobe:vfs_read,\n    kprobe:vfs_write\n{\n    @[probe] = count();\n}\n";
+        let clauses = parse_clauses(program);
+        assert_eq!(clauses.len(), 1);
+        assert_eq!(clauses[0].probes, vec!["kprobe:vfs_read", "kprobe:vfs_write"]);
+        assert_eq!(
+            add_predicate(program, "pid == 42"),
+            "kprobe:vfs_read,\n    kprobe:vfs_write /pid == 42/\n{\n    @[probe] = count();\n}\n"
+        );
+    }
+
+    #[test]
+    fn skips_begin_end_and_interval() {
+        let program = "BEGIN { printf(\"start\\n\"); }\n\
+                       kprobe:vfs_read { @ = count(); }\n\
+                       interval:s:1 { print(@); }\n\
+                       END { clear(@); }";
+        assert_eq!(
+            add_predicate(program, "pid == 42"),
+            "BEGIN { printf(\"start\\n\"); }\n\
+             kprobe:vfs_read /pid == 42/ { @ = count(); }\n\
+             interval:s:1 { print(@); }\n\
+             END { clear(@); }"
+        );
+    }
+
+    #[test]
+    fn skips_config_include_and_struct_prologue() {
+        let program = "#include <linux/sched.h>\n\
+                       config = { perf_rb_pages = 1024 }\n\
+                       struct foo { int a; };\n\
+                       // kprobe:commented_out { }\n\
+                       kprobe:vfs_read { @ = count(); }";
+        let clauses = parse_clauses(program);
+        assert_eq!(clauses.len(), 1);
+        assert_eq!(clauses[0].probes, vec!["kprobe:vfs_read"]);
+        assert!(add_predicate(program, "pid == 42").ends_with("kprobe:vfs_read /pid == 42/ { @ = count(); }"));
+        assert!(add_predicate(program, "pid == 42").starts_with("#include <linux/sched.h>\nconfig = { perf_rb_pages = 1024 }\n"));
+    }
+
+    #[test]
+    fn rewrites_every_task_bound_clause() {
+        let program = "kprobe:a { } kprobe:b /arg0/ { }";
+        assert_eq!(
+            add_predicate(program, "pid == 1"),
+            "kprobe:a /pid == 1/ { } kprobe:b /(pid == 1) && (arg0)/ { }"
+        );
+    }
+
+    #[test]
+    fn detects_process_context_probes() {
+        let needs = |program: &str| parse_clauses(program)[0].needs_process_context();
+        assert!(needs("usdt:/usr/sbin/nginx:probe { }"));
+        assert!(needs("uprobe:libc:malloc { }"));
+        assert!(!needs("uprobe:/usr/lib/libc.so.6:malloc { }"));
+        assert!(!needs("kprobe:vfs_read { }"));
+    }
+}
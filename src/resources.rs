@@ -0,0 +1,85 @@
+// URIs for execution output exposed as MCP resources:
+//
+//   trace://executions/<id>/output                 full output
+//   trace://executions/<id>/output?offset=N&limit=M  a line range
+//
+// Subscriptions are always keyed by the URI without the query string.
+
+pub const OUTPUT_URI_TEMPLATE: &str = "trace://executions/{execution_id}/output{?offset,limit}";
+
+const PREFIX: &str = "trace://executions/";
+const SUFFIX: &str = "/output";
+
+pub fn output_uri(execution_id: &str) -> String {
+    format!("{}{}{}", PREFIX, execution_id, SUFFIX)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputRequest {
+    pub execution_id: String,
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+pub fn parse_output_uri(uri: &str) -> Option<OutputRequest> {
+    let (path, query) = match uri.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (uri, None),
+    };
+    let execution_id = path.strip_prefix(PREFIX)?.strip_suffix(SUFFIX)?;
+    if execution_id.is_empty() || execution_id.contains('/') {
+        return None;
+    }
+
+    let mut request = OutputRequest {
+        execution_id: execution_id.to_string(),
+        offset: 0,
+        limit: None,
+    };
+    for pair in query.unwrap_or_default().split('&').filter(|p| !p.is_empty()) {
+        let (key, value) = pair.split_once('=')?;
+        match key {
+            "offset" => request.offset = value.parse().ok()?,
+            "limit" => request.limit = Some(value.parse().ok()?),
+            _ => return None,
+        }
+    }
+    Some(request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_output_uri() {
+        let uri = output_uri("exec_1234abcd");
+        assert_eq!(uri, "trace://executions/exec_1234abcd/output");
+        assert_eq!(
+            parse_output_uri(&uri),
+            Some(OutputRequest {
+                execution_id: "exec_1234abcd".to_string(),
+                offset: 0,
+                limit: None,
+            })
+        );
+    }
+
+    #[test]
+    fn parses_line_range() {
+        let request = parse_output_uri("trace://executions/exec_1/output?offset=20&limit=5").unwrap();
+        assert_eq!((request.offset, request.limit), (20, Some(5)));
+        let request = parse_output_uri("trace://executions/exec_1/output?limit=5").unwrap();
+        assert_eq!((request.offset, request.limit), (0, Some(5)));
+    }
+
+    #[test]
+    fn rejects_malformed_uris() {
+        assert_eq!(parse_output_uri("trace://executions//output"), None);
+        assert_eq!(parse_output_uri("trace://executions/a/b/output"), None);
+        assert_eq!(parse_output_uri("trace://executions/exec_1/stderr"), None);
+        assert_eq!(parse_output_uri("trace://executions/exec_1/output?offset=x"), None);
+        assert_eq!(parse_output_uri("trace://executions/exec_1/output?follow=1"), None);
+        assert_eq!(parse_output_uri("file:///etc/passwd"), None);
+    }
+}
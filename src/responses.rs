@@ -0,0 +1,224 @@
+// Typed responses of the JSON-returning tools. Each tool serialises one of
+// these as structured content and declares its JSON Schema as the tool's
+// output schema; the schemas are also readable as `trace://schemas/<tool>`
+// resources.
+
+use crate::{
+    attach::AttachReport,
+    bundle::{ReplayInfo, SystemInfo},
+    helpers::{HelperExample, RelatedProgram},
+    perf::PerfMode,
+    summary::{KeyCount, RateBucket, ValueStats},
+    BpftraceServer,
+};
+use rmcp::{
+    handler::server::{router::tool::ToolRouter, tool::schema_for_type},
+    model::{CallToolResult, Content, JsonObject},
+    schemars::{self, JsonSchema},
+};
+use serde::Serialize;
+use std::sync::Arc;
+
+pub const SCHEMA_URI_PREFIX: &str = "trace://schemas/";
+
+pub fn schema_uri(tool: &str) -> String {
+    format!("{}{}", SCHEMA_URI_PREFIX, tool)
+}
+
+// Tool name and output schema for every tool with a structured response
+pub fn output_schemas() -> Vec<(&'static str, JsonObject)> {
+    vec![
+        ("list_probes", schema_for_type::<ListProbesResponse>()),
+        ("exec_program", schema_for_type::<ExecProgramResponse>()),
+        ("perf_trace", schema_for_type::<PerfTraceResponse>()),
+        ("get_result", schema_for_type::<GetResultResponse>()),
+        ("list_executions", schema_for_type::<ListExecutionsResponse>()),
+        ("get_execution_info", schema_for_type::<ExecutionInfoResponse>()),
+        ("summarize_execution", schema_for_type::<SummarizeExecutionResponse>()),
+        ("get_helper_examples", schema_for_type::<HelperExamplesResponse>()),
+        ("export_bundle", schema_for_type::<ExportBundleResponse>()),
+        ("import_bundle", schema_for_type::<ImportBundleResponse>()),
+    ]
+}
+
+pub fn output_schema(tool: &str) -> Option<JsonObject> {
+    output_schemas()
+        .into_iter()
+        .find(|(name, _)| *name == tool)
+        .map(|(_, schema)| schema)
+}
+
+// Declare each tool's output schema in the tool list
+pub fn advertise(router: &mut ToolRouter<BpftraceServer>) {
+    for (tool, schema) in output_schemas() {
+        if let Some(route) = router.map.get_mut(tool) {
+            route.attr.output_schema = Some(Arc::new(schema));
+        }
+    }
+}
+
+// Structured content, plus the same JSON as text for clients that predate
+// structured tool results
+pub fn success<T: Serialize>(response: &T) -> CallToolResult {
+    let text = serde_json::to_string(response).unwrap_or_default();
+    let mut result = CallToolResult::structured(serde_json::to_value(response).unwrap_or_default());
+    result.content = Some(vec![Content::text(text)]);
+    result
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ListProbesResponse {
+    #[schemars(description = "Probe names as printed by `bpftrace -l`")]
+    pub probes: Vec<String>,
+    pub count: usize,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ExecProgramResponse {
+    pub execution_id: String,
+    #[schemars(description = "Always 'started'; poll get_result for progress")]
+    pub status: String,
+    pub message: String,
+    pub target_comm: Option<String>,
+    #[schemars(description = "PIDs the program was restricted to, empty for system-wide tracing")]
+    pub target_pids: Vec<u32>,
+    #[schemars(description = "Whether one bpftrace instance was attached per target PID; only then are output lines tagged with '[pid N]'")]
+    pub per_pid_attach: bool,
+    pub long_running: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PerfTraceResponse {
+    pub execution_id: String,
+    #[schemars(description = "Always 'started'; poll get_result for progress")]
+    pub status: String,
+    #[schemars(description = "Always 'perf'")]
+    pub backend: String,
+    pub mode: PerfMode,
+    pub message: String,
+    pub target_comm: Option<String>,
+    pub target_pids: Vec<u32>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct GetResultResponse {
+    pub execution_id: String,
+    #[schemars(description = "One of 'running', 'completed', 'failed' or 'interrupted'")]
+    pub status: String,
+    pub progress_percent: u64,
+    pub time_remaining_secs: u64,
+    pub lines_total: usize,
+    pub lines_returned: usize,
+    pub output: Vec<String>,
+    #[schemars(description = "Whether lines exist past offset + lines_returned")]
+    pub has_more: bool,
+    pub error_message: Option<String>,
+    #[schemars(description = "Run time in seconds, once finished")]
+    pub duration: Option<u64>,
+    #[schemars(description = "Events dropped because the perf ring buffer overflowed")]
+    pub lost_events: u64,
+    pub lost_events_warning: Option<String>,
+    #[schemars(description = "File holding the full output of a long-running execution")]
+    pub spill_path: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ExecutionSummary {
+    pub execution_id: String,
+    pub status: String,
+    #[schemars(description = "Unix timestamp in seconds")]
+    pub creation_time: u64,
+    pub lines_total: usize,
+    pub progress_percent: u64,
+    pub time_remaining_secs: u64,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ListExecutionsResponse {
+    #[schemars(description = "Executions ordered by creation time")]
+    pub executions: Vec<ExecutionSummary>,
+    pub count: usize,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ExecutionInfoResponse {
+    pub execution_id: String,
+    pub status: String,
+    #[schemars(description = "Program as run, including any injected PID predicate (the perf command lines for perf_trace)")]
+    pub program: String,
+    pub timeout: u64,
+    #[schemars(description = "Unix timestamp in seconds")]
+    pub creation_time: u64,
+    #[schemars(description = "Unix timestamp in seconds, once finished")]
+    pub completion_time: Option<u64>,
+    pub progress_percent: u64,
+    pub time_remaining_secs: u64,
+    pub error_message: Option<String>,
+    pub target_pids: Vec<u32>,
+    pub per_pid_attach: bool,
+    pub long_running: bool,
+    pub export_metrics: bool,
+    pub lost_events: u64,
+    pub attach_report: AttachReport,
+    #[schemars(description = "Bundle this execution was imported from; absent for executions run here")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replay_of: Option<ReplayInfo>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SummarizeExecutionResponse {
+    pub execution_id: String,
+    pub status: String,
+    pub lines_total: usize,
+    #[schemars(description = "Output lines counted as events (server markers and attach messages excluded)")]
+    pub events: usize,
+    #[schemars(description = "Whether output beyond the in-memory line cap or the spill size cap was dropped, so keys and values cover only the kept lines")]
+    pub output_truncated: bool,
+    pub bucket_secs: u64,
+    #[schemars(description = "Events per second over time, one entry per bucket of bucket_secs")]
+    pub rate: Vec<RateBucket>,
+    pub key_field: Option<String>,
+    #[schemars(description = "Most frequent keys, highest count first")]
+    pub top_keys: Vec<KeyCount>,
+    pub value_stats: Option<ValueStats>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct HelperExamplesResponse {
+    pub query: String,
+    #[schemars(description = "Curated snippets, best match first")]
+    pub examples: Vec<HelperExample>,
+    #[schemars(description = "Complete bundled programs using the helper or matching the task")]
+    pub related_programs: Vec<RelatedProgram>,
+    #[schemars(description = "Helper names with examples, listed when nothing matched")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub available_helpers: Vec<String>,
+    #[schemars(description = "Task keywords with examples, listed when nothing matched")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub available_tasks: Vec<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ExportBundleResponse {
+    pub execution_id: String,
+    #[schemars(description = "Bundle file written on the server")]
+    pub path: String,
+    pub bytes: u64,
+    pub lines: usize,
+    pub maps: usize,
+    pub histograms: usize,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ImportBundleResponse {
+    #[schemars(description = "ID of the replayed execution; use it with get_result, summarize_execution and get_execution_info")]
+    pub execution_id: String,
+    pub original_execution_id: String,
+    #[schemars(description = "True when this export was imported before; execution_id is then the existing replay")]
+    pub already_imported: bool,
+    pub status: String,
+    pub program: String,
+    pub lines: usize,
+    pub exported_at: u64,
+    pub system_info: SystemInfo,
+}
@@ -0,0 +1,180 @@
+// On-disk copy of an execution's full output, used by long-running
+// executions whose output outgrows the in-memory line buffer.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::{AsyncBufReadExt, AsyncSeekExt, AsyncWriteExt, BufReader, Lines, SeekFrom},
+    sync::Mutex,
+};
+
+// Lines between entries of the offset index
+const INDEX_INTERVAL: usize = 1000;
+
+#[derive(Debug, Clone)]
+pub struct SpillFile {
+    path: PathBuf,
+    file: Arc<Mutex<File>>,
+    lines: Arc<Mutex<usize>>,
+    bytes: Arc<Mutex<u64>>,
+    // Byte offset of every INDEX_INTERVAL-th line, so reading a page of a
+    // long trace seeks close to it instead of scanning from the start
+    index: Arc<Mutex<Vec<u64>>>,
+    // Output past this size is dropped, like lines past the in-memory cap
+    max_bytes: u64,
+}
+
+impl SpillFile {
+    pub async fn create(dir: &Path, execution_id: &str, max_bytes: u64) -> io::Result<Self> {
+        crate::config::ensure_private_dir(dir)?;
+        let path = dir.join(format!("{}.log", execution_id));
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(&path)
+            .await?;
+        Ok(Self {
+            path,
+            file: Arc::new(Mutex::new(file)),
+            lines: Arc::new(Mutex::new(0)),
+            bytes: Arc::new(Mutex::new(0)),
+            index: Arc::new(Mutex::new(Vec::new())),
+            max_bytes,
+        })
+    }
+
+    // Reattach to a spill file left by a previous server process
+    pub async fn open_existing(path: PathBuf) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(&path).await?);
+        let mut line = Vec::new();
+        let mut lines = 0;
+        let mut bytes = 0;
+        let mut index = Vec::new();
+        loop {
+            line.clear();
+            let size = reader.read_until(b'\n', &mut line).await?;
+            if size == 0 {
+                break;
+            }
+            if lines % INDEX_INTERVAL == 0 {
+                index.push(bytes);
+            }
+            lines += 1;
+            bytes += size as u64;
+        }
+        let file = OpenOptions::new().append(true).open(&path).await?;
+        Ok(Self {
+            path,
+            file: Arc::new(Mutex::new(file)),
+            lines: Arc::new(Mutex::new(lines)),
+            bytes: Arc::new(Mutex::new(bytes)),
+            index: Arc::new(Mutex::new(index)),
+            // Nothing appends to a recovered execution's output
+            max_bytes: u64::MAX,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub async fn append(&self, line: &str) -> io::Result<()> {
+        let mut file = self.file.lock().await;
+        let mut bytes = self.bytes.lock().await;
+        if *bytes >= self.max_bytes {
+            return Ok(());
+        }
+        let offset = *bytes;
+        let size = line.len() as u64 + 1;
+        let line = if *bytes + size > self.max_bytes {
+            // Marks the output as truncated and stops further writes
+            *bytes = self.max_bytes;
+            format!("[Output truncated at {} bytes]\n", self.max_bytes)
+        } else {
+            *bytes += size;
+            format!("{}\n", line)
+        };
+        file.write_all(line.as_bytes()).await?;
+        file.flush().await?;
+        let mut lines = self.lines.lock().await;
+        if *lines % INDEX_INTERVAL == 0 {
+            self.index.lock().await.push(offset);
+        }
+        *lines += 1;
+        Ok(())
+    }
+
+    pub async fn truncated(&self) -> bool {
+        *self.bytes.lock().await >= self.max_bytes
+    }
+
+    pub async fn line_count(&self) -> usize {
+        *self.lines.lock().await
+    }
+
+    pub async fn lines(&self) -> io::Result<Lines<BufReader<File>>> {
+        Ok(BufReader::new(File::open(&self.path).await?).lines())
+    }
+
+    pub async fn read_range(&self, offset: usize, limit: usize) -> io::Result<Vec<String>> {
+        let mut file = File::open(&self.path).await?;
+        // Start from the closest indexed line at or before `offset`
+        let mut index = offset / INDEX_INTERVAL * INDEX_INTERVAL;
+        match self.index.lock().await.get(offset / INDEX_INTERVAL) {
+            Some(&position) => {
+                file.seek(SeekFrom::Start(position)).await?;
+            }
+            None => index = 0,
+        }
+        let mut reader = BufReader::new(file).lines();
+        let mut lines = Vec::new();
+        while let Some(line) = reader.next_line().await? {
+            if index >= offset.saturating_add(limit) {
+                break;
+            }
+            if index >= offset {
+                lines.push(line);
+            }
+            index += 1;
+        }
+        Ok(lines)
+    }
+
+    pub async fn remove(&self) {
+        let _ = fs::remove_file(&self.path).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reads_ranges_across_index_entries() {
+        let dir = std::env::temp_dir().join(format!("bpftrace-mcp-spill-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let spill = SpillFile::create(&dir, "exec_index", u64::MAX).await.unwrap();
+        for i in 0..2500 {
+            spill.append(&format!("line {}", i)).await.unwrap();
+        }
+        assert_eq!(spill.index.lock().await.len(), 3);
+
+        let page = spill.read_range(995, 10).await.unwrap();
+        assert_eq!(page.first().unwrap(), "line 995");
+        assert_eq!(page.last().unwrap(), "line 1004");
+        assert_eq!(spill.read_range(2000, 1).await.unwrap(), vec!["line 2000"]);
+        assert_eq!(spill.read_range(2495, 100).await.unwrap().len(), 5);
+        assert!(spill.read_range(3000, 10).await.unwrap().is_empty());
+
+        // A reopened spill file rebuilds the same index
+        let reopened = SpillFile::open_existing(spill.path().to_path_buf()).await.unwrap();
+        assert_eq!(*reopened.index.lock().await, *spill.index.lock().await);
+        assert_eq!(reopened.read_range(1999, 2).await.unwrap(), vec!["line 1999", "line 2000"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
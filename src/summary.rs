@@ -0,0 +1,258 @@
+// Cheap aggregates over an execution's output so a large trace can be
+// reasoned about without paging every line through the client.
+//
+// An event is an output line that is not a server marker ("[Error] ...",
+// "[Execution timed out]", ...) or bpftrace's attach message. Fields are
+// picked from events by selector: a number is a whitespace-separated column
+// (0-based), anything else a name matched as `name=value`, `name: value` or
+// a key of a JSON line (top level or under "data").
+
+use crate::output;
+use rmcp::schemars::{self, JsonSchema};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+// Aim for at most this many rate buckets when no bucket size is given
+const TARGET_BUCKETS: u64 = 60;
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct RateBucket {
+    #[schemars(description = "Seconds since the execution started")]
+    pub start_secs: u64,
+    pub events: u64,
+    pub per_second: f64,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct KeyCount {
+    pub key: String,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ValueStats {
+    pub field: String,
+    #[schemars(description = "Events where the field was present and numeric")]
+    pub count: u64,
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+}
+
+// Drop the "[pid N] " tag added for per-PID attachment
+pub fn strip_tag(line: &str) -> &str {
+    if let Some(rest) = line.strip_prefix("[pid ") {
+        if let Some((pid, rest)) = rest.split_once("] ") {
+            if pid.chars().all(|c| c.is_ascii_digit()) {
+                return rest;
+            }
+        }
+    }
+    line
+}
+
+pub fn is_event(line: &str) -> bool {
+    let line = strip_tag(line).trim();
+    !line.is_empty() && !line.starts_with('[') && output::parse_attached_probes(line).is_none()
+}
+
+pub fn field(line: &str, selector: &str) -> Option<String> {
+    let line = strip_tag(line).trim();
+    if let Ok(column) = selector.parse::<usize>() {
+        return line.split_whitespace().nth(column).map(str::to_string);
+    }
+
+    if line.starts_with('{') {
+        let value: Value = serde_json::from_str(line).ok()?;
+        let found = value
+            .get(selector)
+            .or_else(|| value.get("data").and_then(|data| data.get(selector)))?;
+        return Some(match found {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        });
+    }
+
+    let mut tokens = line.split_whitespace();
+    while let Some(token) = tokens.next() {
+        let value = if token == format!("{}:", selector) || token == format!("{}=", selector) {
+            tokens.next()
+        } else {
+            token
+                .strip_prefix(selector)
+                .and_then(|rest| rest.strip_prefix('=').or_else(|| rest.strip_prefix(':')))
+                .filter(|rest| !rest.is_empty())
+        };
+        if let Some(value) = value {
+            return Some(value.trim_end_matches([',', ';']).to_string());
+        }
+    }
+    None
+}
+
+// Counts how often each key occurs, one event at a time, so output of any
+// size can be summarized without holding it in memory
+#[derive(Debug, Default)]
+pub struct KeyCounter {
+    counts: HashMap<String, u64>,
+}
+
+impl KeyCounter {
+    pub fn add(&mut self, line: &str, selector: Option<&str>) {
+        let key = match selector {
+            Some(selector) => match field(line, selector) {
+                Some(key) => key,
+                None => return,
+            },
+            None => strip_tag(line).trim().to_string(),
+        };
+        *self.counts.entry(key).or_default() += 1;
+    }
+
+    pub fn top(self, n: usize) -> Vec<KeyCount> {
+        let mut keys: Vec<KeyCount> = self
+            .counts
+            .into_iter()
+            .map(|(key, count)| KeyCount { key, count })
+            .collect();
+        keys.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.key.cmp(&b.key)));
+        keys.truncate(n);
+        keys
+    }
+}
+
+// Running min/max/avg of a numeric field
+#[derive(Debug)]
+pub struct ValueAccumulator {
+    field: String,
+    count: u64,
+    min: f64,
+    max: f64,
+    sum: f64,
+}
+
+impl ValueAccumulator {
+    pub fn new(selector: &str) -> Self {
+        Self {
+            field: selector.to_string(),
+            count: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            sum: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, line: &str) {
+        let Some(value) = field(line, &self.field)
+            .and_then(|value| value.parse::<f64>().ok())
+            .filter(|value| value.is_finite())
+        else {
+            return;
+        };
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
+    }
+
+    pub fn finish(self) -> Option<ValueStats> {
+        (self.count > 0).then(|| ValueStats {
+            avg: self.sum / self.count as f64,
+            field: self.field,
+            count: self.count,
+            min: self.min,
+            max: self.max,
+        })
+    }
+}
+
+// Fold per-second event counts into buckets of `bucket_secs` (chosen
+// automatically when None); returns the bucket size used and the buckets.
+pub fn rate(per_second: &[u64], bucket_secs: Option<u64>) -> (u64, Vec<RateBucket>) {
+    let seconds = per_second.len() as u64;
+    let bucket_secs = bucket_secs
+        .unwrap_or_else(|| seconds.div_ceil(TARGET_BUCKETS))
+        .max(1);
+
+    let buckets = per_second
+        .chunks(bucket_secs as usize)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let events: u64 = chunk.iter().sum();
+            RateBucket {
+                start_secs: i as u64 * bucket_secs,
+                events,
+                per_second: events as f64 / chunk.len() as f64,
+            }
+        })
+        .collect();
+    (bucket_secs, buckets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selects_fields() {
+        let line = "[pid 42] comm=nginx bytes: 4096 latency_us=12.5,";
+        assert_eq!(field(line, "0").as_deref(), Some("comm=nginx"));
+        assert_eq!(field(line, "comm").as_deref(), Some("nginx"));
+        assert_eq!(field(line, "bytes").as_deref(), Some("4096"));
+        assert_eq!(field(line, "latency_us").as_deref(), Some("12.5"));
+        assert_eq!(field(line, "missing"), None);
+        assert_eq!(field(line, "9"), None);
+    }
+
+    #[test]
+    fn selects_json_fields() {
+        let line = r#"{"type": "printf", "data": {"comm": "nginx", "bytes": 4096}}"#;
+        assert_eq!(field(line, "comm").as_deref(), Some("nginx"));
+        assert_eq!(field(line, "bytes").as_deref(), Some("4096"));
+        assert_eq!(field(line, "type").as_deref(), Some("printf"));
+    }
+
+    #[test]
+    fn skips_markers_and_attach_messages() {
+        assert!(is_event("[pid 7] open /etc/hosts"));
+        assert!(!is_event("Attaching 2 probes..."));
+        assert!(!is_event("[Error] something failed"));
+        assert!(!is_event("[pid 7] [Execution timed out]"));
+        assert!(!is_event("   "));
+    }
+
+    #[test]
+    fn buckets_rates() {
+        let (bucket_secs, buckets) = rate(&[1, 2, 3, 4, 5], Some(2));
+        assert_eq!(bucket_secs, 2);
+        assert_eq!(
+            buckets.iter().map(|b| (b.start_secs, b.events)).collect::<Vec<_>>(),
+            vec![(0, 3), (2, 7), (4, 5)]
+        );
+        assert_eq!(buckets[2].per_second, 5.0);
+
+        // At most 60 buckets when no size is given
+        let (bucket_secs, buckets) = rate(&[1; 150], None);
+        assert_eq!(bucket_secs, 3);
+        assert_eq!(buckets.len(), 50);
+        assert_eq!(rate(&[], None).1.len(), 0);
+    }
+
+    #[test]
+    fn accumulates_keys_and_values() {
+        let lines = ["comm=a ms=3", "comm=b ms=1", "comm=a ms=8", "comm=c ms=x"];
+        let mut keys = KeyCounter::default();
+        let mut values = ValueAccumulator::new("ms");
+        for line in lines {
+            keys.add(line, Some("comm"));
+            values.add(line);
+        }
+        let top = keys.top(2);
+        assert_eq!((top[0].key.as_str(), top[0].count), ("a", 2));
+        assert_eq!((top[1].key.as_str(), top[1].count), ("b", 1));
+        let stats = values.finish().unwrap();
+        assert_eq!((stats.count, stats.min, stats.max, stats.avg), (3, 1.0, 8.0, 4.0));
+        assert!(ValueAccumulator::new("ms").finish().is_none());
+    }
+}
@@ -0,0 +1,78 @@
+use std::fs;
+
+// The kernel truncates task names to TASK_COMM_LEN - 1 bytes
+const COMM_MAX_LEN: usize = 15;
+
+fn comm_matches(proc_comm: &str, target: &str) -> bool {
+    if proc_comm == target {
+        return true;
+    }
+    target.len() > COMM_MAX_LEN && target.get(..COMM_MAX_LEN) == Some(proc_comm)
+}
+
+// Scan /proc for processes whose comm matches `target`, skipping ourselves.
+pub fn find_pids_by_comm(target: &str) -> Vec<u32> {
+    let own_pid = std::process::id();
+    let entries = match fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut pids: Vec<u32> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|pid| *pid != own_pid)
+        .filter(|pid| {
+            fs::read_to_string(format!("/proc/{}/comm", pid))
+                .map(|comm| comm_matches(comm.trim_end_matches('\n'), target))
+                .unwrap_or(false)
+        })
+        .collect();
+
+    pids.sort_unstable();
+    pids
+}
+
+pub fn missing_pids(pids: &[u32]) -> Vec<u32> {
+    pids.iter()
+        .copied()
+        .filter(|pid| fs::metadata(format!("/proc/{}", pid)).is_err())
+        .collect()
+}
+
+pub fn pid_predicate(pids: &[u32]) -> String {
+    pids.iter()
+        .map(|pid| format!("pid == {}", pid))
+        .collect::<Vec<_>>()
+        .join(" || ")
+}
+
+// Direct children of `pid`, e.g. the bpftrace process started by sudo
+pub fn child_pids(pid: u32) -> Vec<u32> {
+    fs::read_to_string(format!("/proc/{}/task/{}/children", pid, pid))
+        .map(|children| {
+            children
+                .split_whitespace()
+                .filter_map(|child| child.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Start time of `pid` in clock ticks since boot (field 22 of
+// /proc/<pid>/stat); together with the PID it identifies one process even
+// after the PID is reused
+pub fn process_start_time(pid: u32) -> Option<u64> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name in field 2 may itself contain spaces and parentheses
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(19)?.parse().ok()
+}
+
+// Whether `pid` is still a running `tracer` (bpftrace or perf) process;
+// the command line check guards against the PID having been reused
+pub fn is_tracer_running(pid: u32, tracer: &str) -> bool {
+    fs::read(format!("/proc/{}/cmdline", pid))
+        .map(|cmdline| String::from_utf8_lossy(&cmdline).contains(tracer))
+        .unwrap_or(false)
+}
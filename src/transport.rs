@@ -0,0 +1,80 @@
+// MCP over a local Unix socket, served next to stdio so another client
+// (an operator's agent, an automation pipeline reaching the host over SSH)
+// sees and manages the same executions. Every connection is its own MCP
+// session.
+
+use crate::BpftraceServer;
+use rmcp::ServiceExt;
+use std::{
+    fs::{self, DirBuilder, Permissions},
+    io,
+    os::unix::{
+        fs::{DirBuilderExt, PermissionsExt},
+        net,
+    },
+    path::Path,
+};
+use tokio::net::UnixListener;
+use tracing::{info, warn};
+
+// Bind a Unix socket that only this user can connect to. Binding creates
+// the socket with umask permissions, so it is bound inside a fresh 0700
+// directory, restricted to 0600 and only then moved into place; there is
+// no window in which anyone else can connect.
+pub fn bind_private(path: &Path) -> io::Result<net::UnixListener> {
+    let parent = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    fs::create_dir_all(parent)?;
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "socket path has no file name"))?;
+    let staging = parent.join(format!(".{}.{}.bind", name.to_string_lossy(), std::process::id()));
+    let _ = fs::remove_dir_all(&staging);
+    DirBuilder::new().mode(0o700).create(&staging)?;
+
+    let staged = staging.join("socket");
+    let result = net::UnixListener::bind(&staged).and_then(|listener| {
+        fs::set_permissions(&staged, Permissions::from_mode(0o600))?;
+        // A previous instance may have left its socket behind
+        let _ = fs::remove_file(path);
+        fs::rename(&staged, path)?;
+        Ok(listener)
+    });
+    let _ = fs::remove_dir_all(&staging);
+    result
+}
+
+pub fn spawn(path: &Path, server: BpftraceServer) -> io::Result<()> {
+    let listener = bind_private(path)?;
+    listener.set_nonblocking(true)?;
+    let listener = UnixListener::from_std(listener)?;
+    // Anyone who can connect can run bpftrace as root
+    info!("Serving MCP on {} in addition to stdio", path.display());
+
+    tokio::spawn(async move {
+        let mut next_session = 1;
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    warn!("MCP socket connection failed: {}", e);
+                    continue;
+                }
+            };
+            let session = next_session;
+            next_session += 1;
+            let server = server.for_session(session);
+            tokio::spawn(async move {
+                info!("MCP socket session {} connected", session);
+                match server.serve(stream.into_split()).await {
+                    Ok(service) => {
+                        let _ = service.waiting().await;
+                    }
+                    Err(e) => warn!("MCP socket session {} failed: {:?}", session, e),
+                }
+                info!("MCP socket session {} closed", session);
+            });
+        }
+    });
+
+    Ok(())
+}